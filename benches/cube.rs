@@ -0,0 +1,78 @@
+//! Compares the slab-based `Cube` intersection with scanning its six faces as quads, the way
+//! cubes used to be intersected. Run with `cargo bench --bench cube`.
+
+use badtracing::materials::{Lambertian, Material};
+use badtracing::objects::{Cube, Hittable, Object, Quad};
+use badtracing::ray::Ray;
+use badtracing::vec3::Vec3;
+use badtracing::{Color, Point3};
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const RAYS: usize = 1_000_000;
+
+fn time<F: FnMut(Ray) -> bool>(rays: &[Ray], mut hit: F) -> (Duration, usize) {
+    let start = Instant::now();
+    let hits = rays.iter().filter(|&&r| hit(black_box(r))).count();
+    (start.elapsed(), hits)
+}
+
+fn main() {
+    let material: Material = Lambertian {
+        albedo: Color::new(0.5, 0.5, 0.5),
+    }
+    .into();
+    let center = Point3::new(0.0, 0.0, 0.0);
+    let radius = 1.0;
+    let (a, b) = (
+        Vec3::new(1.0, 1.0, 0.0).unit_vector(),
+        Vec3::new(0.0, 0.0, 1.0),
+    );
+    let c = a.cross(b);
+    let cube = Cube::new(center, radius, a, b, material.clone());
+
+    // The six faces, built in world space on every ray like the old implementation did.
+    let faces = || -> [Object; 6] {
+        let face = |n: Vec3, o: Vec3| -> Object {
+            let side = n.cross(o);
+            let corner = center + radius * (n - o - side);
+            Quad::new(
+                corner,
+                2.0 * radius * o,
+                2.0 * radius * side,
+                material.clone(),
+            )
+            .into()
+        };
+        [
+            face(a, b),
+            face(-a, b),
+            face(b, c),
+            face(-b, c),
+            face(c, a),
+            face(-c, a),
+        ]
+    };
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    let rays: Vec<Ray> = (0..RAYS)
+        .map(|_| {
+            let orig = 5.0 * Vec3::random_unit_vector(&mut rng);
+            let target = Vec3::random_mm(&mut rng, -1.5, 1.5);
+            Ray::new(orig, target - orig, 0.0)
+        })
+        .collect();
+
+    let (slab, slab_hits) = time(&rays, |r| cube.hit(r, 0.001, f64::INFINITY).is_some());
+    let (scan, scan_hits) = time(&rays, |r| {
+        faces().as_ref().hit(r, 0.001, f64::INFINITY).is_some()
+    });
+    assert_eq!(slab_hits, scan_hits);
+
+    let per_ray = |d: Duration| d.as_nanos() as f64 / RAYS as f64;
+    println!("slabs:      {:8.1} ns/ray", per_ray(slab));
+    println!("face scan:  {:8.1} ns/ray", per_ray(scan));
+    println!("speedup:    {:8.2}x", per_ray(scan) / per_ray(slab));
+}
@@ -0,0 +1,78 @@
+use crate::vec3::{Transform, Vec3};
+use crate::Point3;
+
+/// Axis-aligned bounding box.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    pub fn new(a: Point3, b: Point3) -> Self {
+        Aabb {
+            min: Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    /// Box spanning `center - half_extents` to `center + half_extents`.
+    pub fn centered(center: Point3, half_extents: Vec3) -> Self {
+        Self::new(center - half_extents, center + half_extents)
+    }
+
+    pub fn surrounding(self, other: Self) -> Self {
+        Aabb {
+            min: Point3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Point3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    /// The overlap of both boxes; it may be empty (`min > max` on some axis).
+    pub fn overlap(self, other: Self) -> Self {
+        Aabb {
+            min: Point3::new(
+                self.min.x.max(other.min.x),
+                self.min.y.max(other.min.y),
+                self.min.z.max(other.min.z),
+            ),
+            max: Point3::new(
+                self.max.x.min(other.max.x),
+                self.max.y.min(other.max.y),
+                self.max.z.min(other.max.z),
+            ),
+        }
+    }
+
+    pub fn center(self) -> Point3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn half_extents(self) -> Vec3 {
+        0.5 * (self.max - self.min)
+    }
+
+    /// Box around all eight transformed corners.
+    pub fn transform(self, transform: Transform) -> Self {
+        let corner = |i: usize| {
+            Point3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            )
+        };
+        let first = transform.point(corner(0));
+        (1..8).fold(Aabb::new(first, first), |b, i| {
+            let p = transform.point(corner(i));
+            b.surrounding(Aabb::new(p, p))
+        })
+    }
+}
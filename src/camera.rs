@@ -1,21 +1,223 @@
 use crate::ray::Ray;
 use crate::vec3::Vec3;
-use crate::Point3;
+use crate::{random_f64_mm, Point3};
 use rand::Rng;
+use std::f64::consts::PI;
+
+#[enum_delegate::register]
+pub trait Projection {
+    /// Ray through the point `(s, t)` of the image, with `s` going from left to right and `t`
+    /// from bottom to top, both in `[0, 1]`.
+    fn get_ray<R: Rng>(&self, rng: &mut R, s: f64, t: f64) -> Ray;
+}
+
+#[derive(Debug, Copy, Clone)]
+#[enum_delegate::implement(Projection)]
+pub enum Camera {
+    Perspective(Perspective),
+    Orthographic(Orthographic),
+    Fisheye(Fisheye),
+    Equirectangular(Equirectangular),
+}
+
+impl Camera {
+    /// A thin-lens perspective camera; see `Perspective::new`.
+    pub fn new(
+        look_from: Point3,
+        look_at: Point3,
+        vup: Vec3,
+        vfov: f64,
+        aspect_ratio: f64,
+        aperture: f64,
+        focus_dist: f64,
+    ) -> Self {
+        Perspective::new(
+            look_from,
+            look_at,
+            vup,
+            vfov,
+            aspect_ratio,
+            aperture,
+            focus_dist,
+        )
+        .into()
+    }
+
+    /// The view of one eye of a stereo pair whose eyes are `interaxial` apart.
+    ///
+    /// Perspective eyes look in parallel, with their images shifted so that what is
+    /// `convergence` away appears in the same place for both. An equirectangular camera
+    /// renders omni-directional stereo, moving the eye to the side for every direction it
+    /// looks in. The other projections have no stereo view: moving them to the side gives no
+    /// parallax, or none that looks right. `convergence` must be positive, and may be infinite
+    /// for eyes that converge on the horizon.
+    pub fn for_eye(self, eye: Eye, interaxial: f64, convergence: f64) -> Option<Self> {
+        assert!(
+            convergence > 0.0,
+            "convergence distance must be positive, not {}",
+            convergence
+        );
+        let offset = match eye {
+            Eye::Left => -interaxial / 2.0,
+            Eye::Right => interaxial / 2.0,
+        };
+        match self {
+            Camera::Perspective(c) => {
+                // Keeps the window at the convergence distance where it was.
+                let shift = offset * (1.0 - c.focus_dist / convergence) * c.u;
+                Some(
+                    Perspective {
+                        origin: c.origin + offset * c.u,
+                        lower_left_corner: c.lower_left_corner + shift,
+                        ..c
+                    }
+                    .into(),
+                )
+            }
+            Camera::Equirectangular(c) => Some(
+                Equirectangular {
+                    eye_offset: offset,
+                    ..c
+                }
+                .into(),
+            ),
+            Camera::Orthographic(_) | Camera::Fisheye(_) => None,
+        }
+    }
+
+    /// Keeps the shutter open from `time0` to `time1`, so moving objects get motion blur.
+    pub fn with_shutter(self, time0: f64, time1: f64) -> Self {
+        let shutter = Shutter { time0, time1 };
+        match self {
+            Camera::Perspective(c) => Perspective { shutter, ..c }.into(),
+            Camera::Orthographic(c) => Orthographic { shutter, ..c }.into(),
+            Camera::Fisheye(c) => Fisheye { shutter, ..c }.into(),
+            Camera::Equirectangular(c) => Equirectangular { shutter, ..c }.into(),
+        }
+    }
+}
+
+/// A perspective camera set up like a real one. A scene unit is a metre, and radiance is in
+/// candela per square metre.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PhysicalCamera {
+    /// Focal length of the lens, in millimetres.
+    pub focal_length: f64,
+    /// Size of the sensor, in millimetres.
+    pub sensor_width: f64,
+    pub sensor_height: f64,
+    /// Focal length over the diameter of the aperture.
+    pub f_number: f64,
+    /// How long the shutter stays open, in seconds from time 0.
+    pub shutter_time: f64,
+    pub iso: f64,
+}
+
+impl Default for PhysicalCamera {
+    /// A 50mm lens on a full-frame sensor at f/8, 1/125 s and ISO 100.
+    fn default() -> Self {
+        PhysicalCamera {
+            focal_length: 50.0,
+            sensor_width: 36.0,
+            sensor_height: 24.0,
+            f_number: 8.0,
+            shutter_time: 1.0 / 125.0,
+            iso: 100.0,
+        }
+    }
+}
+
+impl PhysicalCamera {
+    /// Vertical field of view in degrees of an image with `aspect_ratio` cropped from the
+    /// middle of the sensor, focused `focus_dist` away. Focusing closer moves the lens away
+    /// from the sensor, which narrows the view.
+    pub fn vfov(&self, aspect_ratio: f64, focus_dist: f64) -> f64 {
+        let height = self.sensor_height.min(self.sensor_width / aspect_ratio);
+        let focal_length = self.focal_length / 1000.0;
+        let lens_to_sensor = 1.0 / (1.0 / focal_length - 1.0 / focus_dist).max(1e-9);
+        (2.0 * (height / 1000.0 / 2.0).atan2(lens_to_sensor)).to_degrees()
+    }
+
+    /// Diameter of the aperture, in metres.
+    pub fn aperture(&self) -> f64 {
+        self.focal_length / self.f_number / 1000.0
+    }
+
+    /// What radiance is multiplied by to give pixel values, with 1 for the brightest radiance
+    /// that does not saturate the sensor (the saturation-based ISO standard).
+    pub fn exposure(&self) -> f64 {
+        // 1.2 = 78 / (100 · 0.65), for the lens transmittance and vignetting of the standard.
+        self.shutter_time * self.iso / (1.2 * 100.0 * self.f_number * self.f_number)
+    }
+
+    /// The camera focused `focus_dist` away, with its shutter open from time 0.
+    pub fn camera(
+        &self,
+        look_from: Point3,
+        look_at: Point3,
+        vup: Vec3,
+        aspect_ratio: f64,
+        focus_dist: f64,
+    ) -> Camera {
+        Camera::new(
+            look_from,
+            look_at,
+            vup,
+            self.vfov(aspect_ratio, focus_dist),
+            aspect_ratio,
+            self.aperture(),
+            focus_dist,
+        )
+        .with_shutter(0.0, self.shutter_time)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+struct Shutter {
+    time0: f64, // Shutter open
+    time1: f64, // Shutter close
+}
+
+impl Shutter {
+    fn sample<R: Rng>(self, rng: &mut R) -> f64 {
+        if self.time1 > self.time0 {
+            random_f64_mm(rng, self.time0, self.time1)
+        } else {
+            self.time0
+        }
+    }
+}
+
+/// The camera's frame: `u` points right, `v` up and `w` backwards, away from what it looks at.
+fn orientation(look_from: Point3, look_at: Point3, vup: Vec3) -> (Vec3, Vec3, Vec3) {
+    let w = (look_from - look_at).unit_vector();
+    let u = (vup.cross(w)).unit_vector();
+    let v = w.cross(u);
+    (u, v, w)
+}
 
 #[derive(Debug, Copy, Clone)]
-pub struct Camera {
+pub struct Perspective {
     origin: Point3,
     lower_left_corner: Point3,
     horizontal: Vec3,
     vertical: Vec3,
     u: Vec3,
     v: Vec3,
-    w: Vec3,
     lens_radius: f64,
+    focus_dist: f64,
+    shutter: Shutter,
 }
 
-impl Camera {
+impl Perspective {
+    /// `vfov` is the vertical field of view in degrees. Points `focus_dist` away are in focus,
+    /// and the rest is blurred the more the wider the `aperture`.
     pub fn new(
         look_from: Point3,
         look_at: Point3,
@@ -30,9 +232,7 @@ impl Camera {
         let viewport_height = 2.0 * h;
         let viewport_width = aspect_ratio * viewport_height;
 
-        let w = (look_from - look_at).unit_vector();
-        let u = (vup.cross(w)).unit_vector();
-        let v = w.cross(u);
+        let (u, v, w) = orientation(look_from, look_at, vup);
 
         let origin = look_from;
         let horizontal = focus_dist * viewport_width * u;
@@ -41,24 +241,287 @@ impl Camera {
 
         let lens_radius = aperture / 2.0;
 
-        Camera {
+        Perspective {
             origin,
             lower_left_corner,
             horizontal,
             vertical,
             u,
             v,
-            w,
             lens_radius,
+            focus_dist,
+            shutter: Shutter::default(),
         }
     }
+}
 
-    pub fn get_ray<R: Rng>(self, rng: &mut R, s: f64, t: f64) -> Ray {
+impl Projection for Perspective {
+    fn get_ray<R: Rng>(&self, rng: &mut R, s: f64, t: f64) -> Ray {
         let rd = self.lens_radius * Point3::random_in_unit_disk(rng);
         let offset = self.u * rd.x + self.v * rd.y;
+        let time = self.shutter.sample(rng);
         Ray::new(
             self.origin + offset,
             self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
+            time,
         )
     }
 }
+
+/// Parallel rays, so objects keep their size however far away they are.
+#[derive(Debug, Copy, Clone)]
+pub struct Orthographic {
+    lower_left_corner: Point3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    direction: Vec3,
+    shutter: Shutter,
+}
+
+impl Orthographic {
+    /// Rays start on a rectangle `height` scene units tall around `look_from` and travel
+    /// towards `look_at`, so nothing behind `look_from` is seen.
+    pub fn new(
+        look_from: Point3,
+        look_at: Point3,
+        vup: Vec3,
+        height: f64,
+        aspect_ratio: f64,
+    ) -> Self {
+        let (u, v, w) = orientation(look_from, look_at, vup);
+        let horizontal = aspect_ratio * height * u;
+        let vertical = height * v;
+        Orthographic {
+            lower_left_corner: look_from - horizontal / 2.0 - vertical / 2.0,
+            horizontal,
+            vertical,
+            direction: -w,
+            shutter: Shutter::default(),
+        }
+    }
+}
+
+impl Projection for Orthographic {
+    fn get_ray<R: Rng>(&self, rng: &mut R, s: f64, t: f64) -> Ray {
+        let origin = self.lower_left_corner + s * self.horizontal + t * self.vertical;
+        Ray::new(origin, self.direction, self.shutter.sample(rng))
+    }
+}
+
+/// Equidistant fisheye: how far a point is from the center of the image is proportional to
+/// the angle between its ray and the viewing direction.
+#[derive(Debug, Copy, Clone)]
+pub struct Fisheye {
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    fov: f64,
+    aspect_ratio: f64,
+    shutter: Shutter,
+}
+
+impl Fisheye {
+    /// `fov` is the angle in degrees seen across the height of the image, up to 360. The
+    /// corners see further out, but never further back than straight behind.
+    pub fn new(look_from: Point3, look_at: Point3, vup: Vec3, fov: f64, aspect_ratio: f64) -> Self {
+        let (u, v, w) = orientation(look_from, look_at, vup);
+        Fisheye {
+            origin: look_from,
+            u,
+            v,
+            w,
+            fov: fov.to_radians(),
+            aspect_ratio,
+            shutter: Shutter::default(),
+        }
+    }
+}
+
+impl Projection for Fisheye {
+    fn get_ray<R: Rng>(&self, rng: &mut R, s: f64, t: f64) -> Ray {
+        // From the center, with the top and bottom edges at a distance of one.
+        let x = (2.0 * s - 1.0) * self.aspect_ratio;
+        let y = 2.0 * t - 1.0;
+        let theta = (x.hypot(y) * self.fov / 2.0).min(PI);
+        let phi = y.atan2(x);
+        let direction =
+            theta.sin() * (phi.cos() * self.u + phi.sin() * self.v) - theta.cos() * self.w;
+        Ray::new(self.origin, direction, self.shutter.sample(rng))
+    }
+}
+
+/// Sees all around: the image spans 360° of longitude from left to right and 180° of latitude
+/// from bottom to top, as used for VR panoramas. Its aspect ratio should be 2:1.
+#[derive(Debug, Copy, Clone)]
+pub struct Equirectangular {
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    /// How far to the right of `origin` the eye is, when rendering one eye of a stereo pair.
+    eye_offset: f64,
+    shutter: Shutter,
+}
+
+impl Equirectangular {
+    /// `look_at` is in the middle of the image, and `vup` points to its top edge.
+    pub fn new(look_from: Point3, look_at: Point3, vup: Vec3) -> Self {
+        let (u, v, w) = orientation(look_from, look_at, vup);
+        Equirectangular {
+            origin: look_from,
+            u,
+            v,
+            w,
+            eye_offset: 0.0,
+            shutter: Shutter::default(),
+        }
+    }
+}
+
+impl Projection for Equirectangular {
+    fn get_ray<R: Rng>(&self, rng: &mut R, s: f64, t: f64) -> Ray {
+        let longitude = (s - 0.5) * 2.0 * PI;
+        let latitude = (t - 0.5) * PI;
+        let direction = latitude.cos() * (longitude.sin() * self.u - longitude.cos() * self.w)
+            + latitude.sin() * self.v;
+        // The eyes circle around the origin, to the side of where they look. Near the poles
+        // they move closer together, since up and down have no side.
+        let right = longitude.cos() * self.u + longitude.sin() * self.w;
+        let origin = self.origin + self.eye_offset * latitude.cos() * right;
+        Ray::new(origin, direction, self.shutter.sample(rng))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_projections_look_the_same_way() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        let look_from = Point3::new(1.0, 2.0, 3.0);
+        let look_at = Point3::new(1.0, 2.0, -7.0);
+        let vup = Vec3::new(0.0, 1.0, 0.0);
+        let cameras: [Camera; 4] = [
+            Camera::new(look_from, look_at, vup, 40.0, 2.0, 0.0, 10.0),
+            Orthographic::new(look_from, look_at, vup, 4.0, 2.0).into(),
+            Fisheye::new(look_from, look_at, vup, 180.0, 1.0).into(),
+            Equirectangular::new(look_from, look_at, vup).into(),
+        ];
+        let forward = Vec3::new(0.0, 0.0, -1.0);
+        for camera in cameras {
+            let center = camera.get_ray(&mut rng, 0.5, 0.5);
+            assert!((center.dir.unit_vector() - forward).length() < 1e-12);
+            assert!((center.orig - look_from).length() < 1e-12);
+        }
+
+        let right = Vec3::new(1.0, 0.0, 0.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        // The edges of a 180° fisheye look sideways.
+        let fisheye = cameras[2].get_ray(&mut rng, 1.0, 0.5);
+        assert!((fisheye.dir - right).length() < 1e-12);
+        let fisheye = cameras[2].get_ray(&mut rng, 0.5, 0.0);
+        assert!((fisheye.dir + up).length() < 1e-12);
+        // A quarter of the way around the panorama is to the right, and its top edge is up.
+        let equirectangular = cameras[3].get_ray(&mut rng, 0.75, 0.5);
+        assert!((equirectangular.dir - right).length() < 1e-12);
+        let equirectangular = cameras[3].get_ray(&mut rng, 0.3, 1.0);
+        assert!((equirectangular.dir - up).length() < 1e-12);
+
+        let corner = cameras[1].get_ray(&mut rng, 0.0, 1.0);
+        assert!((corner.dir - forward).length() < 1e-12);
+        assert!((corner.orig - Point3::new(-3.0, 4.0, 3.0)).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_stereo_eyes() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        let look_from = Point3::new(0.0, 0.0, 0.0);
+        let look_at = Point3::new(0.0, 0.0, -1.0);
+        let vup = Vec3::new(0.0, 1.0, 0.0);
+        let eyes = |camera: Camera| {
+            [Eye::Left, Eye::Right].map(|eye| camera.for_eye(eye, 0.1, 4.0).unwrap())
+        };
+
+        // Both eyes see the point at the convergence distance in the middle of the image.
+        let perspective = Camera::new(look_from, look_at, vup, 40.0, 1.5, 0.0, 2.0);
+        for camera in eyes(perspective) {
+            let r = camera.get_ray(&mut rng, 0.5, 0.5);
+            let t = 4.0 / -r.dir.z;
+            assert!((r.at(t) - Point3::new(0.0, 0.0, -4.0)).length() < 1e-12);
+        }
+
+        // In every direction, the eyes are side by side.
+        let [left, right] = eyes(Equirectangular::new(look_from, look_at, vup).into());
+        for s in [0.0, 0.3, 0.5, 0.9] {
+            let (l, r) = (
+                left.get_ray(&mut rng, s, 0.5),
+                right.get_ray(&mut rng, s, 0.5),
+            );
+            assert_eq!(l.dir, r.dir);
+            let apart = r.orig - l.orig;
+            assert!((apart.length() - 0.1).abs() < 1e-12);
+            assert!(apart.dot(l.dir).abs() < 1e-12);
+            assert!(apart.cross(l.dir).y > 0.0);
+        }
+        let (l, r) = (
+            left.get_ray(&mut rng, 0.2, 1.0),
+            right.get_ray(&mut rng, 0.2, 1.0),
+        );
+        assert!((r.orig - l.orig).length() < 1e-12);
+
+        // Side by side, parallel projections see the same image.
+        let flat: [Camera; 2] = [
+            Orthographic::new(look_from, look_at, vup, 4.0, 2.0).into(),
+            Fisheye::new(look_from, look_at, vup, 180.0, 1.0).into(),
+        ];
+        for camera in flat {
+            assert!(camera.for_eye(Eye::Left, 0.1, 4.0).is_none());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "convergence distance must be positive")]
+    fn test_eyes_cannot_converge_at_the_camera() {
+        let camera = Camera::new(
+            Point3::default(),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+            1.5,
+            0.0,
+            2.0,
+        );
+        camera.for_eye(Eye::Left, 0.1, 0.0);
+    }
+
+    #[test]
+    fn test_physical_camera() {
+        let camera = PhysicalCamera::default();
+        // A 50mm lens sees about 27° of a 24mm high sensor when focused at infinity.
+        assert!((camera.vfov(1.5, f64::INFINITY) - 26.99).abs() < 0.01);
+        assert!(camera.vfov(1.5, 0.5) < camera.vfov(1.5, 10.0));
+        // A wider image uses the full width of the sensor and crops its height.
+        let wide = camera.vfov(3.0, f64::INFINITY);
+        assert!((wide - 2.0 * 6f64.atan2(50.0).to_degrees()).abs() < 1e-9);
+        assert!((camera.aperture() - 0.00625).abs() < 1e-12);
+
+        // Sunny 16: f/16 at 1/ISO s exposes a sunlit scene the same at every ISO.
+        let sunny = |iso: f64| PhysicalCamera {
+            f_number: 16.0,
+            shutter_time: 1.0 / iso,
+            iso,
+            ..camera
+        };
+        assert!((sunny(100.0).exposure() - sunny(400.0).exposure()).abs() < 1e-15);
+        // One stop more light doubles the exposure.
+        let open = PhysicalCamera {
+            f_number: 8.0 / 2f64.sqrt(),
+            ..camera
+        };
+        assert!((open.exposure() / camera.exposure() - 2.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,65 @@
+use crate::film::{invalid_data, read_u64, write_u64, Film};
+use crate::render::Renderer;
+
+use std::io::{self, Read, Write};
+
+const MAGIC: &[u8; 8] = b"BTCKPT03";
+
+/// The state of a progressive render after some whole number of passes, from which
+/// `Renderer::render_progressive` can carry on.
+///
+/// Every pass takes one sample per pixel, so the pass count is also each pixel's sample count,
+/// and it fixes where the random numbers of the next pass start.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    /// `Renderer::settings` of the render, which resuming must match.
+    pub settings: String,
+    /// `fingerprint` of the camera, which resuming must match too.
+    pub camera: u64,
+    pub passes: u32,
+    pub film: Film,
+}
+
+impl Checkpoint {
+    pub fn write_to<W: Write>(&self, mut out: W) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        write_u64(&mut out, self.settings.len() as u64)?;
+        out.write_all(self.settings.as_bytes())?;
+        write_u64(&mut out, self.camera)?;
+        write_u64(&mut out, self.passes.into())?;
+        self.film.write_to(&mut out)?;
+        out.flush()
+    }
+
+    /// Reads a checkpoint that `renderer` can resume from. Anything else, even a film of
+    /// another size, is rejected before its samples are read.
+    pub fn read_from<R: Read>(mut input: R, renderer: &Renderer) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a checkpoint"));
+        }
+        let length = read_u64(&mut input)?;
+        let mut settings = String::new();
+        input.by_ref().take(length).read_to_string(&mut settings)?;
+        if settings.len() as u64 != length {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if settings != renderer.settings() {
+            return Err(invalid_data("rendered with other settings"));
+        }
+        let camera = read_u64(&mut input)?;
+        let passes = read_u64(&mut input)?
+            .try_into()
+            .map_err(|_| invalid_data("too many passes"))?;
+        let layers = 1 + renderer.aovs.len();
+        let expected = Film::new(renderer.width, renderer.height, layers, renderer.filter);
+        let film = Film::read_expecting(&mut input, &expected)?;
+        Ok(Checkpoint {
+            settings,
+            camera,
+            passes,
+            film,
+        })
+    }
+}
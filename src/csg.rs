@@ -0,0 +1,198 @@
+use crate::aabb::Aabb;
+use crate::objects::{Cube, Hittable, Sphere};
+use crate::ray::Ray;
+use crate::HitRecord;
+
+use std::sync::Arc;
+
+/// A stretch of a ray inside a solid, from the surface where it enters to where it leaves.
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub enter: HitRecord,
+    pub exit: HitRecord,
+}
+
+/// Closed shapes that can report every interval a ray spends inside them.
+#[enum_delegate::register]
+pub trait Solid {
+    /// All spans along the whole line of `r`, including negative `t`, sorted and disjoint.
+    fn spans(&self, r: Ray) -> Vec<Span>;
+}
+
+#[derive(Clone, Debug)]
+#[allow(clippy::large_enum_variant)]
+#[enum_delegate::implement(Solid)]
+pub enum SolidObject {
+    Sphere(Sphere),
+    Cube(Cube),
+    Csg(Csg),
+}
+
+impl SolidObject {
+    fn as_hittable(&self) -> &dyn Hittable {
+        match self {
+            SolidObject::Sphere(s) => s,
+            SolidObject::Cube(c) => c,
+            SolidObject::Csg(c) => c,
+        }
+    }
+}
+
+impl Hittable for SolidObject {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.as_hittable().hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.as_hittable().bounding_box()
+    }
+
+    fn occluded(&self, r: Ray, t_min: f64, t_max: f64) -> bool {
+        self.as_hittable().occluded(r, t_min, t_max)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOp {
+    fn contains(self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOp::Union => in_a || in_b,
+            CsgOp::Intersection => in_a && in_b,
+            CsgOp::Difference => in_a && !in_b,
+        }
+    }
+}
+
+/// Combination of two solids. For a difference, `b` is carved out of `a`.
+#[derive(Clone, Debug)]
+pub struct Csg {
+    op: CsgOp,
+    a: Arc<SolidObject>,
+    b: Arc<SolidObject>,
+}
+
+impl Csg {
+    pub fn new(op: CsgOp, a: Arc<SolidObject>, b: Arc<SolidObject>) -> Self {
+        Self { op, a, b }
+    }
+
+    pub fn union(a: Arc<SolidObject>, b: Arc<SolidObject>) -> Self {
+        Self::new(CsgOp::Union, a, b)
+    }
+
+    pub fn intersection(a: Arc<SolidObject>, b: Arc<SolidObject>) -> Self {
+        Self::new(CsgOp::Intersection, a, b)
+    }
+
+    pub fn difference(a: Arc<SolidObject>, b: Arc<SolidObject>) -> Self {
+        Self::new(CsgOp::Difference, a, b)
+    }
+}
+
+impl Solid for Csg {
+    fn spans(&self, r: Ray) -> Vec<Span> {
+        // Walk the boundaries of both children in order, tracking which of them we are inside.
+        let mut events = Vec::new();
+        for (from_b, spans) in [(false, self.a.spans(r)), (true, self.b.spans(r))] {
+            for span in spans {
+                events.push((span.enter, from_b, true));
+                events.push((span.exit, from_b, false));
+            }
+        }
+        events.sort_by(|x, y| x.0.t.total_cmp(&y.0.t));
+
+        let mut result = Vec::new();
+        let (mut in_a, mut in_b) = (false, false);
+        let mut enter = None;
+        for (mut rec, from_b, entering) in events {
+            let was_inside = self.op.contains(in_a, in_b);
+            if from_b {
+                in_b = entering;
+            } else {
+                in_a = entering;
+            }
+            let inside = self.op.contains(in_a, in_b);
+            if inside == was_inside {
+                continue;
+            }
+            // Surfaces of `b` bound a difference from the other side.
+            if from_b && self.op == CsgOp::Difference {
+                rec.front_face = !rec.front_face;
+            }
+            match enter.take() {
+                None => enter = Some(rec),
+                Some(enter) => result.push(Span { enter, exit: rec }),
+            }
+        }
+        result
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.spans(r)
+            .into_iter()
+            .flat_map(|span| [span.enter, span.exit])
+            .find(|rec| rec.t >= t_min && rec.t <= t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (a, b) = (self.a.bounding_box(), self.b.bounding_box());
+        match self.op {
+            CsgOp::Union => Some(a?.surrounding(b?)),
+            CsgOp::Intersection => match (a, b) {
+                (Some(a), Some(b)) => Some(a.overlap(b)),
+                _ => a.or(b),
+            },
+            CsgOp::Difference => a,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::materials::{Lambertian, Material};
+    use crate::vec3::Vec3;
+    use crate::{Color, Point3};
+
+    #[test]
+    fn test_cube_minus_sphere() {
+        let material: Material = Lambertian {
+            albedo: Color::new(0.5, 0.5, 0.5),
+        }
+        .into();
+        let cube = Cube::new(
+            Point3::default(),
+            1.0,
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            material.clone(),
+        );
+        let sphere = Sphere::new(Point3::default(), 0.5, material);
+        let csg = Csg::difference(Arc::new(cube.into()), Arc::new(sphere.into()));
+
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let spans = csg.spans(r);
+        let ts: Vec<_> = spans.iter().map(|s| (s.enter.t, s.exit.t)).collect();
+        assert_eq!(ts, vec![(4.0, 4.5), (5.5, 6.0)]);
+
+        // Leaving the solid into the carved hollow: the sphere's surface faces us from inside
+        // the cube material, so it is a back face of the result.
+        let rec = csg.hit(r, 4.1, f64::INFINITY).unwrap();
+        assert_eq!(rec.t, 4.5);
+        assert!(!rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+
+        // Hit from inside the hollow: the sphere's wall is now the front of the solid.
+        let rec = csg.hit(r, 5.0, f64::INFINITY).unwrap();
+        assert_eq!(rec.t, 5.5);
+        assert!(rec.front_face);
+    }
+}
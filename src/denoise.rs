@@ -0,0 +1,227 @@
+use crate::render::{Aov, Frame};
+use crate::Color;
+
+use rayon::prelude::*;
+
+/// Edge-avoiding À-Trous wavelet filter. Each pass blurs with a 5×5 B3-spline kernel whose taps
+/// are spread twice as far apart as in the previous pass. Neighbors count less the more their
+/// color, normal or depth differs from the center.
+#[derive(Debug, Copy, Clone)]
+pub struct Denoiser {
+    pub iterations: u32,
+    /// Color differences around this size are smoothed; larger ones are kept as edges. The
+    /// value is halved for every pass, so later, wider passes only remove what noise is left.
+    pub sigma_color: f64,
+    pub sigma_normal: f64,
+    /// Relative to the depth of the center pixel.
+    pub sigma_depth: f64,
+    /// Filters the lighting divided by the albedo, so texture detail is not blurred.
+    pub demodulate: bool,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            sigma_color: 1.0,
+            sigma_normal: 0.3,
+            sigma_depth: 0.1,
+            demodulate: true,
+        }
+    }
+}
+
+impl Denoiser {
+    pub fn with_iterations(self, iterations: u32) -> Self {
+        Self { iterations, ..self }
+    }
+
+    /// Scales how different colors may be and still be blended. Higher is smoother.
+    pub fn with_strength(self, strength: f64) -> Self {
+        Self {
+            sigma_color: strength * Denoiser::default().sigma_color,
+            ..self
+        }
+    }
+
+    pub fn with_demodulation(self, demodulate: bool) -> Self {
+        Self { demodulate, ..self }
+    }
+
+    /// Denoised beauty image, top row first. Returns `None` unless the frame has the
+    /// albedo, normal and depth passes.
+    pub fn denoise(&self, frame: &Frame) -> Option<Vec<Color>> {
+        let albedo = frame.layer(Aov::Albedo)?;
+        let normal = frame.layer(Aov::Normal)?;
+        let depth: Vec<f64> = frame.layer(Aov::Depth)?.iter().map(|d| d.x).collect();
+
+        let demodulate = |c: Color, a: Color| {
+            let safe = |c: f64, a: f64| if a > 1e-3 { c / a } else { c };
+            Color::new(safe(c.x, a.x), safe(c.y, a.y), safe(c.z, a.z))
+        };
+        let mut color: Vec<Color> = frame
+            .beauty
+            .iter()
+            .zip(albedo)
+            .map(|(c, a)| {
+                if self.demodulate {
+                    demodulate(*c, *a)
+                } else {
+                    *c
+                }
+            })
+            .collect();
+
+        for i in 0..self.iterations {
+            let step = 1 << i;
+            let sigma_color = self.sigma_color / (1 << i) as f64;
+            color = (0..frame.height)
+                .into_par_iter()
+                .flat_map_iter(|y| {
+                    let (color, depth) = (&color, &depth);
+                    (0..frame.width).map(move |x| {
+                        let guides = Guides {
+                            color,
+                            normal,
+                            depth,
+                            width: frame.width,
+                            height: frame.height,
+                        };
+                        self.filter_pixel(guides, x, y, step, sigma_color)
+                    })
+                })
+                .collect();
+        }
+
+        if self.demodulate {
+            color = color
+                .iter()
+                .zip(albedo)
+                .map(|(c, a)| {
+                    let remodulate = |c: f64, a: f64| if a > 1e-3 { c * a } else { c };
+                    Color::new(
+                        remodulate(c.x, a.x),
+                        remodulate(c.y, a.y),
+                        remodulate(c.z, a.z),
+                    )
+                })
+                .collect();
+        }
+        Some(color)
+    }
+
+    fn filter_pixel(&self, g: Guides, x: usize, y: usize, step: usize, sigma_color: f64) -> Color {
+        const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+        let p = y * g.width + x;
+        let (cp, np, dp) = (g.color[p], g.normal[p], g.depth[p]);
+        let mut sum = Color::default();
+        let mut total = 0.0;
+        for (ky, wy) in KERNEL.iter().enumerate() {
+            let qy = y as isize + (ky as isize - 2) * step as isize;
+            if qy < 0 || qy >= g.height as isize {
+                continue;
+            }
+            for (kx, wx) in KERNEL.iter().enumerate() {
+                let qx = x as isize + (kx as isize - 2) * step as isize;
+                if qx < 0 || qx >= g.width as isize {
+                    continue;
+                }
+                let q = qy as usize * g.width + qx as usize;
+                let cq = g.color[q];
+                let w_color = (-(cp - cq).length_squared() / (sigma_color * sigma_color)).exp();
+                let w_normal = (-(np - g.normal[q]).length_squared()
+                    / (self.sigma_normal * self.sigma_normal))
+                    .exp();
+                let depth_scale = self.sigma_depth * dp.max(1e-3) * step as f64;
+                let w_depth = (-(dp - g.depth[q]).abs() / depth_scale).exp();
+                let w = wx * wy * w_color * w_normal * w_depth;
+                sum += w * cq;
+                total += w;
+            }
+        }
+        // The center pixel always has a positive weight.
+        sum / total
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Guides<'a> {
+    color: &'a [Color],
+    normal: &'a [Color],
+    depth: &'a [f64],
+    width: usize,
+    height: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::Stats;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    /// A noisy frame whose left half faces one way and is dark, while the right half faces
+    /// another way and is bright.
+    fn split_frame() -> Frame {
+        let (width, height) = (32, 32);
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut beauty = Vec::new();
+        let mut normal = Vec::new();
+        for _y in 0..height {
+            for x in 0..width {
+                let (base, n) = if x < width / 2 {
+                    (0.2, Color::new(0.0, 1.0, 0.0))
+                } else {
+                    (0.8, Color::new(1.0, 0.0, 0.0))
+                };
+                let noise = rng.random::<f64>() - 0.5;
+                beauty.push(Color::new(base + noise, base + noise, base + noise));
+                normal.push(n);
+            }
+        }
+        let flat = |c: Color| vec![c; width * height];
+        Frame {
+            width,
+            height,
+            samples_per_pixel: 1,
+            beauty,
+            layers: vec![
+                (Aov::Albedo, flat(Color::new(1.0, 1.0, 1.0))),
+                (Aov::Normal, normal),
+                (Aov::Depth, flat(Color::new(5.0, 5.0, 5.0))),
+            ],
+            stats: Stats::default(),
+        }
+    }
+
+    #[test]
+    fn test_denoise_smooths_and_keeps_edges() {
+        let frame = split_frame();
+        let denoised = Denoiser::default().denoise(&frame).unwrap();
+
+        let error = |pixels: &[Color]| {
+            pixels
+                .iter()
+                .enumerate()
+                .map(|(i, c)| {
+                    let expected = if i % frame.width < frame.width / 2 {
+                        0.2
+                    } else {
+                        0.8
+                    };
+                    (c.x - expected).powi(2)
+                })
+                .sum::<f64>()
+        };
+        assert!(error(&denoised) < 0.1 * error(&frame.beauty));
+        // The normal edge stops the two halves from bleeding into each other.
+        let row = &denoised[16 * frame.width..17 * frame.width];
+        assert!(row[15].x < 0.35 && row[16].x > 0.65);
+
+        let bare = Frame {
+            layers: Vec::new(),
+            ..frame
+        };
+        assert!(Denoiser::default().denoise(&bare).is_none());
+    }
+}
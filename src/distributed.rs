@@ -0,0 +1,399 @@
+use crate::camera::Camera;
+use crate::film::{invalid_data, read_u64, write_u64, Film};
+use crate::merge::fingerprint;
+use crate::render::{Frame, Renderer, TileMerger};
+use crate::scene::Scene;
+use crate::tiles::Tile;
+
+use std::collections::VecDeque;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const MAGIC: &[u8; 8] = b"BTWORK03";
+const JOB: u64 = 0;
+const DONE: u64 = 1;
+
+/// Renders an image with the help of worker processes connecting to `listener`, and returns
+/// it once every tile is back.
+///
+/// Each worker is sent `seed`, from which it must build the same scene, and the renderer
+/// settings and a fingerprint of `camera`, which it must have too. It is then handed one tile at a time to render with all
+/// the samples. Tiles render the same wherever they are rendered and are merged in order, so
+/// the image is exactly what `Renderer::render` would give. When a worker disconnects, sends
+/// back something else or goes quiet for `tile_timeout` while rendering a tile, it is dropped
+/// and its tile goes to the next free worker.
+///
+/// `on_tile` is called with every tile that arrives and its film, which also holds the
+/// statistics of the worker that rendered it.
+pub fn coordinate<F>(
+    renderer: &Renderer,
+    camera: &Camera,
+    listener: &TcpListener,
+    seed: u64,
+    tile_timeout: Duration,
+    on_tile: F,
+) -> io::Result<Frame>
+where
+    F: Fn(Tile, &Film) + Sync,
+{
+    let tiles = renderer.tiles();
+    let layers = 1 + renderer.aovs.len();
+    let film = Film::new(renderer.width, renderer.height, layers, renderer.filter);
+    let work = Work {
+        state: Mutex::new(WorkState {
+            queue: (0..tiles.len()).collect(),
+            merger: TileMerger::new(film, tiles.len()),
+            cancelled: false,
+        }),
+        changed: Condvar::new(),
+    };
+    let mut hello = MAGIC.to_vec();
+    write_u64(&mut hello, seed)?;
+    write_u64(&mut hello, fingerprint(camera))?;
+    let settings = renderer.settings();
+    write_u64(&mut hello, settings.len() as u64)?;
+    hello.extend_from_slice(settings.as_bytes());
+
+    // Polls for workers so it can stop taking them once the image is done.
+    listener.set_nonblocking(true)?;
+    let film = thread::scope(|s| loop {
+        {
+            let mut state = work.state.lock().unwrap();
+            if state.merger.is_done() {
+                let film = Film::new(renderer.width, renderer.height, layers, renderer.filter);
+                break Ok(std::mem::replace(&mut state.merger.film, film));
+            }
+        }
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let (work, hello, tiles, on_tile) = (&work, &hello, &tiles, &on_tile);
+                // A worker that fails has already handed its tile back.
+                s.spawn(move || serve(stream, renderer, work, hello, tiles, tile_timeout, on_tile));
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(10))
+            }
+            Err(e) => {
+                // Lets the workers connected so far go.
+                work.state.lock().unwrap().cancelled = true;
+                work.changed.notify_all();
+                break Err(e);
+            }
+        }
+    })?;
+    Ok(renderer.frame(&film))
+}
+
+/// The tiles still to hand out, and the ones that came back.
+struct Work {
+    state: Mutex<WorkState>,
+    changed: Condvar,
+}
+
+struct WorkState {
+    queue: VecDeque<usize>,
+    merger: TileMerger,
+    cancelled: bool,
+}
+
+impl Work {
+    /// Waits for a tile to render, which is `None` once all of them are done.
+    fn take(&self) -> Option<usize> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.cancelled {
+                return None;
+            }
+            if let Some(index) = state.queue.pop_front() {
+                return Some(index);
+            }
+            if state.merger.is_done() {
+                return None;
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+
+    fn finish(&self, index: usize, film: Film) {
+        self.state.lock().unwrap().merger.add(index, film);
+        self.changed.notify_all();
+    }
+
+    fn give_back(&self, index: usize) {
+        self.state.lock().unwrap().queue.push_front(index);
+        self.changed.notify_all();
+    }
+}
+
+/// Hands tiles to one worker until there are none left.
+fn serve<F>(
+    stream: TcpStream,
+    renderer: &Renderer,
+    work: &Work,
+    hello: &[u8],
+    tiles: &[Tile],
+    tile_timeout: Duration,
+    on_tile: &F,
+) -> io::Result<()>
+where
+    F: Fn(Tile, &Film),
+{
+    stream.set_nonblocking(false)?;
+    // A worker whose machine is gone may never close the connection.
+    stream.set_read_timeout(Some(tile_timeout))?;
+    stream.set_write_timeout(Some(tile_timeout))?;
+    let mut input = BufReader::new(stream.try_clone()?);
+    let mut out = BufWriter::new(stream);
+    out.write_all(hello)?;
+    out.flush()?;
+    while let Some(index) = work.take() {
+        match request(&mut input, &mut out, renderer, tiles[index], index) {
+            Ok(film) => {
+                on_tile(tiles[index], &film);
+                work.finish(index, film);
+            }
+            Err(e) => {
+                work.give_back(index);
+                return Err(e);
+            }
+        }
+    }
+    write_u64(&mut out, DONE)?;
+    out.flush()
+}
+
+/// Has the worker render one tile.
+fn request<R: Read, W: Write>(
+    input: &mut R,
+    out: &mut W,
+    renderer: &Renderer,
+    tile: Tile,
+    index: usize,
+) -> io::Result<Film> {
+    write_u64(out, JOB)?;
+    write_u64(out, index as u64)?;
+    write_u64(out, renderer.samples_per_pixel.into())?;
+    out.flush()?;
+    if read_u64(input)? != index as u64 {
+        return Err(invalid_data("worker sent back the wrong tile"));
+    }
+    let layers = 1 + renderer.aovs.len();
+    let (width, height, filter) = (renderer.width, renderer.height, renderer.filter);
+    let expected = Film::for_tile(width, height, layers, filter, tile);
+    Film::read_expecting(input, &expected)
+}
+
+/// A worker process's connection to the coordinator.
+pub struct Worker {
+    input: BufReader<TcpStream>,
+    out: BufWriter<TcpStream>,
+    /// What the coordinator built its scene from.
+    pub seed: u64,
+    /// `fingerprint` of the coordinator's camera.
+    pub camera: u64,
+    /// `Renderer::settings` of the coordinator.
+    pub settings: String,
+}
+
+impl Worker {
+    pub fn connect<A: ToSocketAddrs>(coordinator: A) -> io::Result<Self> {
+        let stream = TcpStream::connect(coordinator)?;
+        let mut input = BufReader::new(stream.try_clone()?);
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a coordinator"));
+        }
+        let seed = read_u64(&mut input)?;
+        let camera = read_u64(&mut input)?;
+        let length = read_u64(&mut input)?;
+        let mut settings = String::new();
+        input.by_ref().take(length).read_to_string(&mut settings)?;
+        Ok(Worker {
+            input,
+            out: BufWriter::new(stream),
+            seed,
+            camera,
+            settings,
+        })
+    }
+
+    /// Renders the tiles the coordinator asks for until it has no more, and returns how many
+    /// that was. The renderer and camera must be the coordinator's; the number of samples is
+    /// the coordinator's either way.
+    pub fn run(mut self, renderer: &Renderer, scene: &Scene, camera: &Camera) -> io::Result<usize> {
+        if renderer.settings() != self.settings {
+            return Err(invalid_data("the coordinator renders with other settings"));
+        }
+        if fingerprint(camera) != self.camera {
+            return Err(invalid_data(
+                "the coordinator renders through another camera",
+            ));
+        }
+        let tiles = renderer.tiles();
+        let layers = 1 + renderer.aovs.len();
+        let mut rendered = 0;
+        while read_u64(&mut self.input)? == JOB {
+            let index = read_u64(&mut self.input)?;
+            let samples = read_u64(&mut self.input)?
+                .try_into()
+                .map_err(|_| invalid_data("too many samples"))?;
+            let tile = *tiles
+                .get(index as usize)
+                .ok_or_else(|| invalid_data("no such tile"))?;
+            let film = renderer.render_tile(scene, camera, tile, layers, 0, samples);
+            write_u64(&mut self.out, index)?;
+            film.write_to(&mut self.out)?;
+            self.out.flush()?;
+            rendered += 1;
+        }
+        Ok(rendered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Eye;
+    use crate::film::Filter;
+    use crate::materials::Lambertian;
+    use crate::objects::Sphere;
+    use crate::render::Aov;
+    use crate::tiles::TileOrder;
+    use crate::vec3::Vec3;
+    use crate::{Color, Point3};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn sphere(seed: u64) -> (Scene, Camera) {
+        let albedo = Color::new(0.2, 0.4, seed as f64 / 10.0);
+        let scene = Scene::new(vec![Sphere::new(
+            Point3::default(),
+            1.0,
+            Lambertian { albedo }.into(),
+        )
+        .into()]);
+        let camera = Camera::new(
+            Point3::new(0.0, 0.0, 4.0),
+            Point3::default(),
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+            1.5,
+            0.0,
+            4.0,
+        );
+        (scene, camera)
+    }
+
+    #[test]
+    fn test_workers_render_the_same_image() {
+        let renderer = Renderer::new(12, 8)
+            .with_samples(2)
+            .with_aovs(&[Aov::Normal])
+            .with_filter(Filter::mitchell(1.0))
+            .with_tiles(4, TileOrder::Spiral);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let arrived = AtomicUsize::new(0);
+        let (scene, camera) = sphere(7);
+
+        let (frame, rendered) = thread::scope(|s| {
+            let workers = s.spawn(|| {
+                // Dies with a tile taken.
+                let mut flaky = Worker::connect(address).unwrap();
+                assert_eq!(read_u64(&mut flaky.input).unwrap(), JOB);
+                drop(flaky);
+
+                let other = Renderer::new(12, 8);
+                let wrong = Worker::connect(address).unwrap();
+                let (scene, camera) = sphere(wrong.seed);
+                assert!(wrong.run(&other, &scene, &camera).is_err());
+
+                let wrong = Worker::connect(address).unwrap();
+                let (scene, camera) = sphere(wrong.seed);
+                let moved = camera.for_eye(Eye::Left, 0.1, 4.0).unwrap();
+                assert!(wrong.run(&renderer, &scene, &moved).is_err());
+
+                let worker = Worker::connect(address).unwrap();
+                let (scene, camera) = sphere(worker.seed);
+                worker.run(&renderer, &scene, &camera).unwrap()
+            });
+            let timeout = Duration::from_secs(60);
+            let frame = coordinate(&renderer, &camera, &listener, 7, timeout, |_, _| {
+                arrived.fetch_add(1, Ordering::Relaxed);
+            })
+            .unwrap();
+            (frame, workers.join().unwrap())
+        });
+        assert_eq!(rendered, 3 * 2);
+        assert_eq!(arrived.into_inner(), 3 * 2);
+
+        let local = renderer.render(&scene, &camera, |_, _| {});
+        assert_eq!(frame.beauty, local.beauty);
+        assert_eq!(frame.layers, local.layers);
+        // The work done by the workers comes back with their tiles.
+        assert_eq!(frame.stats, local.stats);
+        if cfg!(feature = "stats") {
+            assert_eq!(frame.stats.camera_rays, 12 * 8 * 2);
+        }
+    }
+
+    #[test]
+    fn test_stalled_worker_loses_its_tile() {
+        let renderer = Renderer::new(8, 8)
+            .with_samples(1)
+            .with_tiles(4, TileOrder::Scanline);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (scene, camera) = sphere(7);
+
+        let frame = thread::scope(|s| {
+            let coordinator = s.spawn(|| {
+                let timeout = Duration::from_millis(200);
+                coordinate(&renderer, &camera, &listener, 7, timeout, |_, _| {}).unwrap()
+            });
+            // Takes a tile and neither answers nor hangs up until the image is done.
+            let mut stalled = Worker::connect(address).unwrap();
+            assert_eq!(read_u64(&mut stalled.input).unwrap(), JOB);
+            let worker = Worker::connect(address).unwrap();
+            let (scene, camera) = sphere(worker.seed);
+            assert_eq!(worker.run(&renderer, &scene, &camera).unwrap(), 4);
+            let frame = coordinator.join().unwrap();
+            drop(stalled);
+            frame
+        });
+
+        assert_eq!(
+            frame.beauty,
+            renderer.render(&scene, &camera, |_, _| {}).beauty
+        );
+    }
+
+    #[test]
+    fn test_film_of_the_wrong_shape_is_rejected_before_reading_it() {
+        let filter = Filter::default();
+        let tile = Tile {
+            x0: 0,
+            y0: 0,
+            x1: 4,
+            y1: 4,
+        };
+        let expected = Film::for_tile(8, 8, 1, filter, tile);
+        let mut bytes = Vec::new();
+        Film::for_tile(8, 8, 1, filter, tile)
+            .write_to(&mut bytes)
+            .unwrap();
+        assert!(Film::read_expecting(bytes.as_slice(), &expected).is_ok());
+
+        // Claims to be a huge image, with no samples to back it.
+        let mut huge = Vec::new();
+        for n in [1 << 40, 1 << 40, 1] {
+            write_u64(&mut huge, n).unwrap();
+        }
+        huge.extend_from_slice(&bytes[24..]);
+        let e = Film::read_expecting(huge.as_slice(), &expected).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
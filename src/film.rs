@@ -0,0 +1,468 @@
+use crate::stats::Stats;
+use crate::tiles::Tile;
+use crate::Color;
+
+use std::f64::consts::PI;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+/// Pixel reconstruction filter. Each sample contributes to every pixel whose center lies
+/// within `radius` of it horizontally and vertically, weighted by the filter's value there.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Filter {
+    Box {
+        radius: f64,
+    },
+    Tent {
+        radius: f64,
+    },
+    /// Gaussian falloff `exp(-alpha x²)`, shifted down to reach zero at the radius.
+    Gaussian {
+        radius: f64,
+        alpha: f64,
+    },
+    /// Mitchell–Netravali cubic; `b = c = 1/3` is the usual compromise between blur and ringing.
+    Mitchell {
+        radius: f64,
+        b: f64,
+        c: f64,
+    },
+    /// Sinc windowed by a wider sinc with `tau` lobes.
+    Lanczos {
+        radius: f64,
+        tau: f64,
+    },
+}
+
+impl Default for Filter {
+    /// A box exactly one pixel wide, which averages the samples in each pixel.
+    fn default() -> Self {
+        Filter::Box { radius: 0.5 }
+    }
+}
+
+impl Filter {
+    pub fn gaussian(radius: f64) -> Self {
+        Filter::Gaussian { radius, alpha: 2.0 }
+    }
+
+    pub fn mitchell(radius: f64) -> Self {
+        Filter::Mitchell {
+            radius,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        }
+    }
+
+    pub fn lanczos(radius: f64) -> Self {
+        Filter::Lanczos { radius, tau: 3.0 }
+    }
+
+    pub fn radius(self) -> f64 {
+        match self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius, .. } => radius,
+        }
+    }
+
+    /// Weight of a sample `dx` and `dy` pixels away from a pixel center.
+    pub fn evaluate(self, dx: f64, dy: f64) -> f64 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(self, x: f64) -> f64 {
+        let x = x.abs();
+        match self {
+            Filter::Box { radius } => {
+                if x <= radius {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Filter::Tent { radius } => (radius - x).max(0.0),
+            Filter::Gaussian { radius, alpha } => {
+                ((-alpha * x * x).exp() - (-alpha * radius * radius).exp()).max(0.0)
+            }
+            Filter::Mitchell { radius, b, c } => {
+                // The cubic is defined on [0, 2].
+                let x = 2.0 * x / radius;
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                } else if x < 2.0 {
+                    ((-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                } else {
+                    0.0
+                }
+            }
+            Filter::Lanczos { radius, tau } => {
+                if x > radius {
+                    0.0
+                } else {
+                    sinc(x) * sinc(x / tau)
+                }
+            }
+        }
+    }
+}
+
+/// Parses `box`, `tent`, `gaussian`, `mitchell` or `lanczos`, optionally followed by the radius
+/// as in `gaussian:1.5`.
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, radius) = match s.split_once(':') {
+            Some((name, radius)) => (
+                name,
+                Some(
+                    radius
+                        .parse::<f64>()
+                        .map_err(|e| format!("invalid radius for {}: {}", name, e))?,
+                ),
+            ),
+            None => (s, None),
+        };
+        Ok(match name {
+            "box" => Filter::Box {
+                radius: radius.unwrap_or(0.5),
+            },
+            "tent" => Filter::Tent {
+                radius: radius.unwrap_or(1.0),
+            },
+            "gaussian" => Filter::gaussian(radius.unwrap_or(1.5)),
+            "mitchell" => Filter::mitchell(radius.unwrap_or(2.0)),
+            "lanczos" => Filter::lanczos(radius.unwrap_or(3.0)),
+            _ => return Err(format!("unknown filter: {}", s)),
+        })
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Filter-weighted sums of samples, optionally covering only a region of the image.
+///
+/// Every sample carries the same number of layers, such as the beauty value followed by AOVs,
+/// which share one weight per pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub layers: usize,
+    pub filter: Filter,
+    region: Tile,
+    sums: Vec<Color>,
+    weights: Vec<f64>,
+    /// The work that went into the samples.
+    pub stats: Stats,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize, layers: usize, filter: Filter) -> Self {
+        let whole = Tile {
+            x0: 0,
+            y0: 0,
+            x1: width,
+            y1: height,
+        };
+        Self::region(width, height, layers, filter, whole)
+    }
+
+    /// Film holding only `region` of the image. Samples may still fall outside of it; only
+    /// their contributions to the stored pixels are kept.
+    pub fn region(
+        width: usize,
+        height: usize,
+        layers: usize,
+        filter: Filter,
+        region: Tile,
+    ) -> Self {
+        let pixels = region.width() * region.height();
+        Self {
+            width,
+            height,
+            layers,
+            filter,
+            region,
+            sums: vec![Color::default(); pixels * layers],
+            weights: vec![0.0; pixels],
+            stats: Stats::default(),
+        }
+    }
+
+    /// Film for the samples taken in `tile`, padded by the filter radius so it receives all of
+    /// their contributions.
+    pub fn for_tile(
+        width: usize,
+        height: usize,
+        layers: usize,
+        filter: Filter,
+        tile: Tile,
+    ) -> Self {
+        let reach = filter.radius().ceil() as usize;
+        let region = tile.padded(reach, width, height);
+        Self::region(width, height, layers, filter, region)
+    }
+
+    /// The pixels stored, which `layer` returns.
+    pub fn stored_region(&self) -> Tile {
+        self.region
+    }
+
+    /// Adds a sample at raster position `(x, y)`, measured in pixels from the top left corner
+    /// of the image, so the center of the top left pixel is at `(0.5, 0.5)`.
+    pub fn add_sample(&mut self, x: f64, y: f64, values: &[Color]) {
+        debug_assert_eq!(values.len(), self.layers);
+        let r = self.filter.radius();
+        // Pixels whose center is within [-r, r) of the sample.
+        let range = |v: f64, start: usize, end: usize| {
+            let first = ((v - 0.5 - r).floor() + 1.0).max(start as f64) as usize;
+            let last = ((v - 0.5 + r).floor() + 1.0).clamp(0.0, end as f64) as usize;
+            first..last
+        };
+        let region = self.region;
+        for py in range(y, region.y0, region.y1) {
+            let wy = self.filter.evaluate_1d(y - (py as f64 + 0.5));
+            for px in range(x, region.x0, region.x1) {
+                let weight = wy * self.filter.evaluate_1d(x - (px as f64 + 0.5));
+                if weight == 0.0 {
+                    continue;
+                }
+                let index = self.index(px, py);
+                self.weights[index] += weight;
+                let sums = &mut self.sums[index * self.layers..(index + 1) * self.layers];
+                for (sum, value) in sums.iter_mut().zip(values) {
+                    *sum += weight * *value;
+                }
+            }
+        }
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        (y - self.region.y0) * self.region.width() + x - self.region.x0
+    }
+
+    /// Adds the samples of another film of the same image, such as one tile.
+    pub fn merge(&mut self, other: &Film) {
+        debug_assert_eq!((self.width, self.layers), (other.width, other.layers));
+        self.stats += other.stats;
+        let overlap = self.region.overlap(other.region);
+        for y in overlap.y0..overlap.y1 {
+            for x in overlap.x0..overlap.x1 {
+                let (mine, theirs) = (self.index(x, y), other.index(x, y));
+                self.weights[mine] += other.weights[theirs];
+                for l in 0..self.layers {
+                    self.sums[mine * self.layers + l] += other.sums[theirs * self.layers + l];
+                }
+            }
+        }
+    }
+
+    /// Weighted average of one layer over the stored region, top row first. Pixels without
+    /// samples are black, and so are those whose samples weigh nothing or less, which the
+    /// negative lobes of some filters give after few samples.
+    pub fn layer(&self, layer: usize) -> Vec<Color> {
+        self.weights
+            .iter()
+            .enumerate()
+            .map(|(i, w)| {
+                if *w <= 0.0 {
+                    Color::default()
+                } else {
+                    self.sums[i * self.layers + layer] / *w
+                }
+            })
+            .collect()
+    }
+
+    /// Writes the exact sums and weights, so `read_from` gives back an identical film.
+    pub fn write_to<W: Write>(&self, mut out: W) -> io::Result<()> {
+        for n in [self.width, self.height, self.layers] {
+            write_u64(&mut out, n as u64)?;
+        }
+        let (tag, params) = match self.filter {
+            Filter::Box { radius } => (0, [radius, 0.0, 0.0]),
+            Filter::Tent { radius } => (1, [radius, 0.0, 0.0]),
+            Filter::Gaussian { radius, alpha } => (2, [radius, alpha, 0.0]),
+            Filter::Mitchell { radius, b, c } => (3, [radius, b, c]),
+            Filter::Lanczos { radius, tau } => (4, [radius, tau, 0.0]),
+        };
+        write_u64(&mut out, tag)?;
+        for param in params {
+            write_f64(&mut out, param)?;
+        }
+        let region = self.region;
+        for n in [region.x0, region.y0, region.x1, region.y1] {
+            write_u64(&mut out, n as u64)?;
+        }
+        for sum in &self.sums {
+            for v in [sum.x, sum.y, sum.z] {
+                write_f64(&mut out, v)?;
+            }
+        }
+        for weight in &self.weights {
+            write_f64(&mut out, *weight)?;
+        }
+        self.stats.write_to(&mut out)
+    }
+
+    pub fn read_from<R: Read>(input: R) -> io::Result<Self> {
+        Self::read_with(input, None)
+    }
+
+    /// Like `read_from`, but fails without reading the samples unless the film has the size,
+    /// layers, filter and stored region of `expected`, so a broken peer cannot make it
+    /// allocate any amount of memory.
+    pub(crate) fn read_expecting<R: Read>(input: R, expected: &Film) -> io::Result<Self> {
+        Self::read_with(input, Some(expected))
+    }
+
+    fn read_with<R: Read>(mut input: R, expected: Option<&Film>) -> io::Result<Self> {
+        let width = read_u64(&mut input)? as usize;
+        let height = read_u64(&mut input)? as usize;
+        let layers = read_u64(&mut input)? as usize;
+        let tag = read_u64(&mut input)?;
+        let radius = read_f64(&mut input)?;
+        let (p1, p2) = (read_f64(&mut input)?, read_f64(&mut input)?);
+        let filter = match tag {
+            0 => Filter::Box { radius },
+            1 => Filter::Tent { radius },
+            2 => Filter::Gaussian { radius, alpha: p1 },
+            3 => Filter::Mitchell {
+                radius,
+                b: p1,
+                c: p2,
+            },
+            4 => Filter::Lanczos { radius, tau: p1 },
+            _ => return Err(invalid_data("unknown filter")),
+        };
+        let mut coordinate = || read_u64(&mut input).map(|n| n as usize);
+        let region = Tile {
+            x0: coordinate()?,
+            y0: coordinate()?,
+            x1: coordinate()?,
+            y1: coordinate()?,
+        };
+        if region.x0 > region.x1 || region.x1 > width || region.y0 > region.y1 || region.y1 > height
+        {
+            return Err(invalid_data("film region outside of the image"));
+        }
+        if let Some(e) = expected {
+            if (width, height, layers, filter, region)
+                != (e.width, e.height, e.layers, e.filter, e.region)
+            {
+                return Err(invalid_data("film of a different shape"));
+            }
+        }
+        let mut film = Self::region(width, height, layers, filter, region);
+        for sum in &mut film.sums {
+            *sum = Color::new(
+                read_f64(&mut input)?,
+                read_f64(&mut input)?,
+                read_f64(&mut input)?,
+            );
+        }
+        for weight in &mut film.weights {
+            *weight = read_f64(&mut input)?;
+        }
+        film.stats = Stats::read_from(&mut input)?;
+        Ok(film)
+    }
+}
+
+pub(crate) fn write_u64<W: Write>(out: &mut W, n: u64) -> io::Result<()> {
+    out.write_all(&n.to_le_bytes())
+}
+
+pub(crate) fn write_f64<W: Write>(out: &mut W, v: f64) -> io::Result<()> {
+    out.write_all(&v.to_le_bytes())
+}
+
+pub(crate) fn read_u64<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub(crate) fn read_f64<R: Read>(input: &mut R) -> io::Result<f64> {
+    read_u64(input).map(f64::from_bits)
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_box_filter_averages_pixels() {
+        let mut film = Film::new(2, 1, 1, Filter::default());
+        film.add_sample(0.2, 0.5, &[Color::new(1.0, 0.0, 0.0)]);
+        film.add_sample(0.9, 0.1, &[Color::new(3.0, 0.0, 0.0)]);
+        film.add_sample(1.0, 0.5, &[Color::new(5.0, 0.0, 0.0)]);
+        let image = film.layer(0);
+        assert_eq!(image[0].x, 2.0);
+        assert_eq!(image[1].x, 5.0);
+    }
+
+    #[test]
+    fn test_negative_weights_leave_pixels_empty() {
+        let mut film = Film::new(2, 1, 1, Filter::mitchell(2.0));
+        // The second pixel is only in the negative lobe of the sample.
+        film.add_sample(0.0, 0.5, &[Color::new(1.0, 0.0, 0.0)]);
+        assert!(film.weights[1] < 0.0);
+        let image = film.layer(0);
+        assert!((image[0].x - 1.0).abs() < 1e-12);
+        assert_eq!(image[1], Color::default());
+
+        // Far from the sample but still weighed positively, the pixel gets its value.
+        let mut film = Film::new(2, 2, 1, Filter::Tent { radius: 1.0 });
+        film.add_sample(1.47, 1.47, &[Color::new(1.0, 0.0, 0.0)]);
+        assert!(film.weights[0] > 0.0 && film.weights[0] < 1e-3);
+        assert!((film.layer(0)[0].x - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_tile_films_merge_across_boundaries() {
+        let filter = Filter::mitchell(2.0);
+        let samples = [(1.3, 0.7), (2.5, 1.5), (0.1, 2.9), (3.6, 2.2)];
+        let value = |i: usize| [Color::new(i as f64, 1.0, 0.0)];
+
+        let mut whole = Film::new(4, 3, 1, filter);
+        let mut merged = Film::new(4, 3, 1, filter);
+        for (i, (x, y)) in samples.iter().enumerate() {
+            whole.add_sample(*x, *y, &value(i));
+            let (px, py) = (*x as usize, *y as usize);
+            let pixel = Tile {
+                x0: px,
+                y0: py,
+                x1: px + 1,
+                y1: py + 1,
+            };
+            let mut tile = Film::for_tile(4, 3, 1, filter, pixel);
+            tile.add_sample(*x, *y, &value(i));
+            merged.merge(&tile);
+        }
+        for (a, b) in whole.layer(0).iter().zip(merged.layer(0)) {
+            assert!((*a - b).length() < 1e-12);
+        }
+    }
+}
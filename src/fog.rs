@@ -0,0 +1,178 @@
+use crate::ray::Ray;
+use crate::{random_f64, Color, Point3};
+
+use crate::vec3::Vec3;
+use rand::Rng;
+
+/// Homogeneous fog filling the whole scene.
+#[derive(Debug, Copy, Clone)]
+pub struct Fog {
+    pub density: f64,
+    pub albedo: Color,
+}
+
+/// Exponential height fog: `density` at `base_height`, decaying by `falloff` per unit of height.
+#[derive(Debug, Copy, Clone)]
+pub struct HeightFog {
+    pub density: f64,
+    pub falloff: f64,
+    pub base_height: f64,
+    pub albedo: Color,
+}
+
+impl HeightFog {
+    fn density_at(self, y: f64) -> f64 {
+        self.density * (-self.falloff * (y - self.base_height)).exp()
+    }
+
+    fn optical_depth(self, orig: Point3, unit_dir: Vec3, s: f64) -> f64 {
+        let a = self.density_at(orig.y);
+        let k = self.falloff * unit_dir.y;
+        if a == 0.0 {
+            0.0
+        } else if k.abs() < 1e-12 {
+            a * s
+        } else {
+            -a * (-k * s).exp_m1() / k
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Atmosphere {
+    pub fog: Option<Fog>,
+    pub height_fog: Option<HeightFog>,
+}
+
+impl Atmosphere {
+    pub fn is_clear(self) -> bool {
+        self.fog.is_none_or(|f| f.density <= 0.0)
+            && self.height_fog.is_none_or(|f| f.density <= 0.0)
+    }
+
+    pub fn density_at(self, p: Point3) -> f64 {
+        self.fog.map_or(0.0, |f| f.density) + self.height_fog.map_or(0.0, |f| f.density_at(p.y))
+    }
+
+    /// Single scattering albedo at `p`, the mix of both fogs weighted by their densities.
+    pub fn albedo_at(self, p: Point3) -> Color {
+        let mut total = 0.0;
+        let mut albedo = Color::default();
+        if let Some(f) = self.fog {
+            total += f.density;
+            albedo += f.density * f.albedo;
+        }
+        if let Some(f) = self.height_fog {
+            let d = f.density_at(p.y);
+            total += d;
+            albedo += d * f.albedo;
+        }
+        if total > 0.0 {
+            albedo / total
+        } else {
+            Color::default()
+        }
+    }
+
+    fn optical_depth(self, orig: Point3, unit_dir: Vec3, s: f64) -> f64 {
+        let mut tau = 0.0;
+        if let Some(f) = self.fog.filter(|f| f.density > 0.0) {
+            tau += f.density * s;
+        }
+        if let Some(f) = self.height_fog.filter(|f| f.density > 0.0) {
+            tau += f.optical_depth(orig, unit_dir, s);
+        }
+        tau
+    }
+
+    /// Fraction of light surviving along `r` between `t = 0` and `t_max`.
+    pub fn transmittance(self, r: Ray, t_max: f64) -> f64 {
+        if self.is_clear() {
+            return 1.0;
+        }
+        let len = r.dir.length();
+        (-self.optical_depth(r.orig, r.dir / len, t_max * len)).exp()
+    }
+
+    /// Samples a free-flight distance along `r`, returning the ray parameter of the scattering
+    /// event if it happens before `t_max`.
+    pub fn sample_distance<R: Rng>(self, rng: &mut R, r: Ray, t_max: f64) -> Option<f64> {
+        if self.is_clear() {
+            return None;
+        }
+        let len = r.dir.length();
+        let unit_dir = r.dir / len;
+        let s_max = t_max * len;
+        let target = -(1.0 - random_f64(rng)).ln();
+        let tau = |s: f64| self.optical_depth(r.orig, unit_dir, s);
+        if tau(s_max) <= target {
+            return None;
+        }
+
+        // Bracket the root, then refine with Newton steps safeguarded by bisection.
+        let mut lo = 0.0;
+        let mut hi = s_max;
+        if hi.is_infinite() {
+            hi = 1.0;
+            while tau(hi) < target {
+                lo = hi;
+                hi *= 2.0;
+            }
+        }
+        let mut s = 0.5 * (lo + hi);
+        for _ in 0..64 {
+            let f = tau(s) - target;
+            if f.abs() < 1e-9 * target.max(1.0) {
+                break;
+            }
+            if f < 0.0 {
+                lo = s;
+            } else {
+                hi = s;
+            }
+            let next = s - f / self.density_at(r.orig + s * unit_dir);
+            s = if next > lo && next < hi {
+                next
+            } else {
+                0.5 * (lo + hi)
+            };
+        }
+        Some(s / len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_homogeneous_transmittance() {
+        let atmosphere = Atmosphere {
+            fog: Some(Fog {
+                density: 0.5,
+                albedo: Color::new(1.0, 1.0, 1.0),
+            }),
+            height_fog: None,
+        };
+        let r = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, -2.0), 0.0);
+        assert!((atmosphere.transmittance(r, 1.0) - (-1.0f64).exp()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_height_fog_optical_depth() {
+        let fog = HeightFog {
+            density: 0.3,
+            falloff: 0.7,
+            base_height: 0.0,
+            albedo: Color::new(1.0, 1.0, 1.0),
+        };
+        let orig = Point3::new(0.0, 1.0, 0.0);
+        let dir = Vec3::new(1.0, 0.5, 0.0).unit_vector();
+        let steps = 10000;
+        let ds = 5.0 / steps as f64;
+        let numeric: f64 = (0..steps)
+            .map(|i| fog.density_at((orig + (i as f64 + 0.5) * ds * dir).y) * ds)
+            .sum();
+        assert!((fog.optical_depth(orig, dir, 5.0) - numeric).abs() < 1e-6);
+    }
+}
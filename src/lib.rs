@@ -1,28 +1,45 @@
 use rand::Rng;
-
-use objects::Hittable;
+use std::f64::consts::PI;
 
 use crate::materials::{Material, MaterialProperties};
 use crate::ray::Ray;
+use crate::scene::Scene;
 use crate::vec3::Vec3;
 
+pub mod aabb;
 pub mod camera;
+pub mod checkpoint;
+pub mod csg;
+pub mod denoise;
+pub mod distributed;
+pub mod film;
+pub mod fog;
+pub mod lights;
 pub mod materials;
+pub mod merge;
 pub mod objects;
+pub mod primitives;
 pub mod ray;
+pub mod render;
+pub mod scene;
+pub mod sdf;
+pub mod stats;
+pub mod tiles;
 pub mod vec3;
 
 pub type Point3 = Vec3;
 pub type UnitVec3 = Vec3;
 pub type Color = Vec3;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct HitRecord {
     pub p: Point3,
     pub normal: UnitVec3,
     pub material: Material,
     pub t: f64,
     pub front_face: bool,
+    pub u: f64,
+    pub v: f64,
 }
 
 impl HitRecord {
@@ -39,49 +56,275 @@ impl HitRecord {
             material: m,
             t,
             front_face,
+            u: 0.0,
+            v: 0.0,
         }
     }
+
+    /// Sets the surface coordinates of the hit point, both in `[0, 1]`.
+    pub fn with_uv(self, u: f64, v: f64) -> Self {
+        HitRecord { u, v, ..self }
+    }
 }
 
 pub fn write_color(pixel_color: Color, samples_per_pixel: i32) {
-    // Divide the color by the number of samples and gamma-correct for gamma=2.0.
-    let scale = 1.0 / samples_per_pixel as f64;
-    let r = (pixel_color.x * scale).sqrt();
-    let g = (pixel_color.y * scale).sqrt();
-    let b = (pixel_color.z * scale).sqrt();
-
-    let ri = (r.clamp(0.0, 0.999) * 256.0) as i32;
-    let gi = (g.clamp(0.0, 0.999) * 256.0) as i32;
-    let bi = (b.clamp(0.0, 0.999) * 256.0) as i32;
+    // Divide the color by the number of samples.
+    let [ri, gi, bi] = to_rgb8(pixel_color / samples_per_pixel as f64);
 
     // Write the translated [0,255] value of each color component.
     println!("{} {} {}", ri, gi, bi)
 }
 
-pub fn ray_color<R: Rng, H: Hittable>(rng: &mut R, r: Ray, world: H, depth: i32) -> Color {
+/// Gamma-corrects for gamma=2.0 and clamps to 8 bits per channel.
+pub fn to_rgb8(pixel_color: Color) -> [u8; 3] {
+    [pixel_color.x, pixel_color.y, pixel_color.z]
+        .map(|c| (c.sqrt().clamp(0.0, 0.999) * 256.0) as u8)
+}
+
+pub fn ray_color<R: Rng>(rng: &mut R, r: Ray, scene: &Scene, depth: i32) -> Color {
+    trace(rng, r, scene, depth, None)
+}
+
+/// Radiance along `r`. After bounces that already sampled the lights directly, `scatter_pdf`
+/// is the density with which `r` was scattered, so that the area lights it hits are weighted
+/// against that sampling rather than counted twice.
+fn trace<R: Rng>(
+    rng: &mut R,
+    r: Ray,
+    scene: &Scene,
+    depth: i32,
+    scatter_pdf: Option<f64>,
+) -> Color {
+    let (emitted, scattered) = trace_split(rng, r, scene, depth, scatter_pdf, &mut None);
+    emitted + scattered
+}
+
+/// The first surface a ray hits, with the light it scatters towards the ray split into light
+/// that comes straight from emitters or the sky and light that bounced on the way.
+#[derive(Debug, Clone)]
+pub(crate) struct PrimaryHit {
+    /// Index of the object hit in `Scene::objects`.
+    pub object: usize,
+    pub rec: HitRecord,
+    pub attenuation: Color,
+    pub direct: Color,
+    pub indirect: Color,
+}
+
+/// Like `trace`, but returns what the first surface or the sky emits separately from what is
+/// scattered there. If a surface is hit before any fog, it is described in `primary`.
+pub(crate) fn trace_split<R: Rng>(
+    rng: &mut R,
+    r: Ray,
+    scene: &Scene,
+    depth: i32,
+    scatter_pdf: Option<f64>,
+    primary: &mut Option<PrimaryHit>,
+) -> (Color, Color) {
     // If we've exceeded the ray bounce limit, no more light is gathered.
     if depth <= 0 {
-        return Color::default();
+        stats::record(|s| s.depth_capped += 1);
+        return (Color::default(), Color::default());
     }
-    match world.hit(r, 0.001, f64::INFINITY) {
-        Some(rec) => match rec.material.scatter(rng, r, rec) {
-            Some((attenuation, scattered)) => {
-                attenuation * ray_color(rng, scattered, world, depth - 1)
+    stats::record(|s| s.rays += 1);
+    let hit = scene.hit(r, 0.001, f64::INFINITY);
+
+    // The ray may scatter off the fog before it reaches the surface or escapes to the sky.
+    let t_max = hit.as_ref().map_or(f64::INFINITY, |(_, rec)| rec.t);
+    if let Some(t) = scene.atmosphere.sample_distance(rng, r, t_max) {
+        let p = r.at(t);
+        let albedo = scene.atmosphere.albedo_at(p);
+        // Isotropic phase function: lights are weighted by it, and sampling it uniformly
+        // makes the scattered ray's weight one.
+        let pdf = 1.0 / (4.0 * PI);
+        let direct = scene.direct_light(rng, p, r.time, |_| pdf);
+        let scattered = Ray::new(p, Vec3::random_unit_vector(rng), r.time);
+        let indirect = trace(rng, scattered, scene, depth - 1, Some(pdf));
+        return (Color::default(), albedo * (direct + indirect));
+    }
+
+    match hit {
+        Some((index, rec)) => {
+            let emitted = scene.emission_weight(index, r, scatter_pdf) * rec.material.emitted(&rec);
+            let mut direct = Color::default();
+            let mut indirect = Color::default();
+            let sampled_lights = match rec.material {
+                Material::Lambertian(l) if !scene.lights.is_empty() => {
+                    let weight = |wi: UnitVec3| rec.normal.dot(wi) / PI;
+                    direct += l.albedo * scene.direct_light(rng, rec.p, r.time, weight);
+                    true
+                }
+                _ => false,
+            };
+            let mut attenuation = Color::default();
+            if let Some((a, scattered)) = rec.material.scatter(rng, r, &rec) {
+                // Diffuse bounces are cosine-weighted.
+                let pdf = sampled_lights
+                    .then(|| rec.normal.dot(scattered.dir.unit_vector()).max(0.0) / PI);
+                let (e, s) = trace_split(rng, scattered, scene, depth - 1, pdf, &mut None);
+                attenuation = a;
+                direct += a * e;
+                indirect += a * s;
+            } else {
+                stats::record(|s| s.absorbed += 1);
             }
-            None => Color::default(),
-        },
+            *primary = Some(PrimaryHit {
+                object: index,
+                rec,
+                attenuation,
+                direct,
+                indirect,
+            });
+            (emitted, direct + indirect)
+        }
         None => {
-            let unit_direction = r.dir.unit_vector();
-            let t = 0.5 * (unit_direction.y + 1.0);
-            (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
+            stats::record(|s| s.missed += 1);
+            (background(r.dir), Color::default())
         }
     }
 }
 
+/// Sky gradient seen by rays that escape the scene.
+pub fn background(dir: Vec3) -> Color {
+    let unit_direction = dir.unit_vector();
+    let t = 0.5 * (unit_direction.y + 1.0);
+    (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
+}
+
 pub fn random_f64<R: Rng>(rng: &mut R) -> f64 {
-    rng.gen()
+    rng.random()
 }
 
 pub fn random_f64_mm<R: Rng>(rng: &mut R, min: f64, max: f64) -> f64 {
-    min + (max - min) * rng.gen::<f64>()
+    min + (max - min) * rng.random::<f64>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aabb::Aabb;
+    use crate::lights::PointLight;
+    use crate::materials::{Custom, CustomMaterial, DiffuseLight, Lambertian};
+    use crate::objects::{CustomObject, Hittable, Object, Quad, Sphere};
+    use rand::rngs::SmallRng;
+    use rand::{RngCore, SeedableRng};
+
+    /// A glowing floor defined the way a downstream crate would.
+    #[derive(Debug)]
+    struct Glow {
+        emit: Color,
+    }
+
+    impl CustomMaterial for Glow {
+        fn scatter(
+            &self,
+            _rng: &mut dyn RngCore,
+            _r: Ray,
+            _rec: &HitRecord,
+        ) -> Option<(Color, Ray)> {
+            None
+        }
+
+        fn emitted(&self, _rec: &HitRecord) -> Color {
+            self.emit
+        }
+    }
+
+    struct Floor(Material);
+
+    impl Hittable for Floor {
+        fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+            let t = -r.orig.y / r.dir.y;
+            if t < t_min || t > t_max {
+                return None;
+            }
+            let normal = Vec3::new(0.0, 1.0, 0.0);
+            Some(HitRecord::new(r.at(t), r.dir, t, normal, self.0.clone()))
+        }
+
+        fn bounding_box(&self) -> Option<Aabb> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_custom_object_and_material() {
+        // Built at runtime, so nothing has to be leaked to give it a 'static lifetime.
+        let emit = Color::new(2.0, 1.0, 0.5);
+        let glow = Custom::new(Glow { emit }).into();
+        let scene = Scene::new(vec![CustomObject::new(Floor(glow)).into()]);
+        let mut rng = SmallRng::seed_from_u64(0);
+        let r = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        assert_eq!(ray_color(&mut rng, r, &scene, 5), emit);
+    }
+
+    fn floor() -> Object {
+        let albedo = Color::new(0.5, 0.5, 0.5);
+        Quad::xz_rect(-50.0, 50.0, -50.0, 50.0, 0.0, Lambertian { albedo }.into()).into()
+    }
+
+    /// Average radiance seen straight down onto the floor, from the same random numbers
+    /// whatever the scene.
+    fn floor_radiance(scene: &Scene, samples: u32) -> Color {
+        let mut rng = SmallRng::seed_from_u64(5);
+        let r = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let total = (0..samples).fold(Color::default(), |sum, _| {
+            sum + ray_color(&mut rng, r, scene, 2)
+        });
+        total / f64::from(samples)
+    }
+
+    #[test]
+    fn test_point_lights_do_not_hide_emitting_objects() {
+        let glow = DiffuseLight {
+            emit: Color::new(4.0, 4.0, 4.0),
+        };
+        let sphere: Object = Sphere::new(Point3::new(1.0, 1.0, 0.0), 0.5, glow.into()).into();
+        let light = PointLight {
+            position: Point3::new(0.0, 3.0, 0.0),
+            intensity: Color::new(9.0, 9.0, 9.0),
+        };
+        let scene = |objects: Vec<Object>, lights: Vec<_>| Scene {
+            objects,
+            lights,
+            ..Default::default()
+        };
+        let both = scene(vec![floor(), sphere.clone()], vec![light.into()]);
+        let point = scene(vec![floor()], vec![light.into()]);
+        let emitter = scene(vec![floor(), sphere], vec![]);
+        let neither = scene(vec![floor()], vec![]);
+
+        // Point lights take no random numbers, so every path is the same in all four scenes.
+        let from_light = floor_radiance(&point, 64) - floor_radiance(&neither, 64);
+        let from_emitter = floor_radiance(&emitter, 64) - floor_radiance(&neither, 64);
+        assert!(from_emitter.x > 0.0);
+        let expected = floor_radiance(&neither, 64) + from_light + from_emitter;
+        assert!((floor_radiance(&both, 64) - expected).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_area_lights_are_weighted_not_double_counted() {
+        // Facing down onto the floor.
+        let glow = DiffuseLight {
+            emit: Color::default(),
+        };
+        let quad = Quad::new(
+            Point3::new(-1.0, 2.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            glow.into(),
+        );
+        let radiance = Color::new(3.0, 3.0, 3.0);
+        let mut sampled = Scene::new(vec![floor()]);
+        sampled.add_area_light(quad.clone(), radiance);
+        // The same emitter, only found by rays bouncing off the floor.
+        let glow = DiffuseLight { emit: radiance };
+        let hit_only = Scene::new(vec![floor(), quad.with_material(glow.into()).into()]);
+
+        let (a, b) = (
+            floor_radiance(&sampled, 20000),
+            floor_radiance(&hit_only, 20000),
+        );
+        assert!((a - b).length() < 0.03 * b.length());
+    }
 }
@@ -0,0 +1,81 @@
+use crate::objects::Quad;
+use crate::{Color, Point3, UnitVec3};
+
+use crate::vec3::Vec3;
+use rand::Rng;
+
+#[enum_delegate::register]
+pub trait LightSource {
+    /// Samples a point on the light and returns the direction towards it, the distance to it
+    /// and the radiance it delivers at `p` divided by the sampling density, ignoring occlusion.
+    fn illuminate<R: Rng>(&self, rng: &mut R, p: Point3) -> (UnitVec3, f64, Color);
+
+    /// Solid angle density with which `illuminate` picks `direction` from `origin`. Lights
+    /// that are a single point or direction, which no scattered ray can hit, return 0.
+    fn pdf_value(&self, _origin: Point3, _direction: Vec3) -> f64 {
+        0.0
+    }
+}
+
+#[derive(Clone, Debug)]
+#[enum_delegate::implement(LightSource)]
+pub enum Light {
+    Point(PointLight),
+    Directional(DirectionalLight),
+    Quad(QuadLight),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PointLight {
+    pub position: Point3,
+    pub intensity: Color,
+}
+
+impl LightSource for PointLight {
+    fn illuminate<R: Rng>(&self, _rng: &mut R, p: Point3) -> (UnitVec3, f64, Color) {
+        let to_light = self.position - p;
+        let dist_squared = to_light.length_squared();
+        let dist = dist_squared.sqrt();
+        (to_light / dist, dist, self.intensity / dist_squared)
+    }
+}
+
+/// A light infinitely far away, like the sun. `direction` is where the light travels to.
+#[derive(Copy, Clone, Debug)]
+pub struct DirectionalLight {
+    pub direction: Vec3,
+    pub radiance: Color,
+}
+
+impl LightSource for DirectionalLight {
+    fn illuminate<R: Rng>(&self, _rng: &mut R, _p: Point3) -> (UnitVec3, f64, Color) {
+        (-self.direction.unit_vector(), f64::INFINITY, self.radiance)
+    }
+}
+
+/// A quad emitting `radiance` from its front face, sampled uniformly by area.
+#[derive(Clone, Debug)]
+pub struct QuadLight {
+    pub quad: Quad,
+    pub radiance: Color,
+}
+
+impl LightSource for QuadLight {
+    fn illuminate<R: Rng>(&self, rng: &mut R, p: Point3) -> (UnitVec3, f64, Color) {
+        let to_light = self.quad.random_point(rng) - p;
+        let dist_squared = to_light.length_squared();
+        let dist = dist_squared.sqrt();
+        let wi = to_light / dist;
+        let cosine = -wi.dot(self.quad.normal());
+        if cosine <= 0.0 {
+            return (wi, dist, Color::default());
+        }
+        // Converts the area density 1/A to solid angle.
+        let scale = cosine * self.quad.area() / dist_squared;
+        (wi, dist, scale * self.radiance)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.quad.pdf_value(origin, direction)
+    }
+}
@@ -1,28 +1,169 @@
 use rand::rngs::SmallRng;
 use rand::SeedableRng;
-use rayon::prelude::*;
 use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::TcpListener;
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::thread;
 
-use badtracing::camera::Camera;
+use badtracing::camera::{Camera, Equirectangular, Eye, Fisheye, Orthographic, PhysicalCamera};
+use badtracing::checkpoint::Checkpoint;
+use badtracing::denoise::Denoiser;
+use badtracing::distributed::{coordinate, Worker};
+use badtracing::film::{Film, Filter};
 use badtracing::materials::{Dielectric, Lambertian, Metal};
+use badtracing::merge::{fingerprint, merge, render_fingerprint, Partial};
 use badtracing::objects::{Cube, Object, Sphere};
+use badtracing::primitives::Plane;
+use badtracing::render::{Aov, Frame, Integrator, Progress, Progressive, Renderer, StereoLayout};
+use badtracing::scene::Scene;
+use badtracing::tiles::TileOrder;
 use badtracing::vec3::Vec3;
-use badtracing::{random_f64, random_f64_mm, ray_color, write_color, Color, Point3};
-use std::sync::atomic::{AtomicI32, Ordering};
-use std::time::UNIX_EPOCH;
+use badtracing::{random_f64, random_f64_mm, write_color, Color, Point3};
+use signal_hook::consts::SIGINT;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
 fn main() {
+    if std::env::args().nth(1).as_deref() == Some("merge") {
+        return merge_renders(std::env::args().skip(2));
+    }
+
     // Image
-    const IMAGE_WIDTH: i32 = 1200;
-    const IMAGE_HEIGHT: i32 = 800;
-    const SAMPLES_PER_PIXEL: i32 = 500;
+    const IMAGE_WIDTH: usize = 1200;
+    const IMAGE_HEIGHT: usize = 800;
+    const SAMPLES_PER_PIXEL: u32 = 500;
     const MAX_DEPTH: i32 = 50;
 
-    // World
-    let world = random_scene();
+    // Render
+    // Usage: badtracing [--denoise] [--filter=name[:radius]] [--tiles=order[:size]]
+    //                   [--spp=N] [--time=SECS] [--progressive [--snapshot-passes=N]
+    //                   [--snapshot-secs=SECS]] [--checkpoint=PATH [--checkpoint-secs=SECS]]
+    //                   [--resume=PATH] [--coordinator=ADDR [--tile-timeout=SECS] | --worker=ADDR]
+    //                   [--world=SEED] [--seed=N] [--partial=PATH] [--camera=name[:param]]
+    //                   [--stereo=layout[:interaxial[:convergence]]]
+    //                   [--physical=focal[:f-number[:shutter[:iso]]]]
+    //                   [integrator] [aov...] > image.ppm
+    //        badtracing merge [--partial=PATH] PARTIAL... > image.ppm
+    // A time budget keeps adding samples to every pixel until the time is up, or up to --spp
+    // if given, and the image header tells how many were taken.
+    // Checkpointing and resuming imply --progressive. A resumed render needs the same
+    // settings and keeps checkpointing to the file it was resumed from.
+    // A coordinator listens on ADDR for workers, which connect to it given the same settings,
+    // render tiles and write nothing themselves. A worker silent for longer than the tile
+    // timeout (10 minutes by default) loses its tile to another. A coordinated render takes
+    // all samples of a tile at once, so it has no time budget, snapshots or checkpoints.
+    // Renders of the same --world with different sample --seeds can be written as --partial
+    // float images and merged into one with all of their samples.
+    // The camera is perspective[:vfov], orthographic[:height], fisheye[:fov] or
+    // equirectangular, which renders a panorama twice as wide as it is tall.
+    // Stereo renders the left and right eyes of a perspective or equirectangular camera
+    // side-by-side or top-bottom in one image. When progressive, the eyes take turns with
+    // each pass, so a stopped render has as many samples in both. It does not work with
+    // checkpoints or distribution.
+    // A physical perspective camera takes its view, depth of field, motion blur and exposure
+    // from a focal length in mm on a full-frame sensor, an f-number, a shutter time in seconds
+    // such as 1/125 and an ISO. The sky of the scene is as bright as at dusk, so try
+    // --physical=50:2:1/2:1000.
+    let (flags, args): (Vec<String>, Vec<String>) = std::env::args()
+        .skip(1)
+        .partition(|arg| arg.starts_with("--"));
+    let mut denoise = false;
+    let mut filter = Filter::default();
+    let mut tile_order = TileOrder::default();
+    let mut tile_size = 32;
+    let mut samples_per_pixel = None;
+    let mut progressive = false;
+    let mut options = Progressive::default();
+    let mut checkpoint_path = None;
+    let mut resume_path = None;
+    let mut coordinator_address = None;
+    let mut tile_timeout = Duration::from_secs(600);
+    let mut worker_address = None;
+    let mut world_seed = None;
+    let mut sample_seed = 0;
+    let mut partial_path = None;
+    let mut projection = "perspective".to_string();
+    let mut stereo = None;
+    let mut physical = None;
+    for flag in flags {
+        match flag.split_once('=') {
+            None if flag == "--denoise" => denoise = true,
+            None if flag == "--progressive" => progressive = true,
+            Some(("--filter", value)) => filter = parse(value),
+            Some(("--tiles", value)) => {
+                let (order, size) = value.split_once(':').unwrap_or((value, "32"));
+                tile_order = parse(order);
+                tile_size = parse(size);
+            }
+            Some(("--spp", value)) => samples_per_pixel = Some(parse(value)),
+            Some(("--time", value)) => options.time_budget = Some(parse_seconds(value)),
+            Some(("--snapshot-passes", value)) => options.snapshot_passes = Some(parse(value)),
+            Some(("--snapshot-secs", value)) => {
+                options.snapshot_interval = Some(parse_seconds(value))
+            }
+            Some(("--checkpoint", value)) => checkpoint_path = Some(value.to_string()),
+            Some(("--checkpoint-secs", value)) => {
+                options.checkpoint_interval = Some(parse_seconds(value))
+            }
+            Some(("--resume", value)) => resume_path = Some(value.to_string()),
+            Some(("--coordinator", value)) => coordinator_address = Some(value.to_string()),
+            Some(("--tile-timeout", value)) => tile_timeout = parse_seconds(value),
+            Some(("--worker", value)) => worker_address = Some(value.to_string()),
+            Some(("--world", value)) => world_seed = Some(parse(value)),
+            Some(("--seed", value)) => sample_seed = parse(value),
+            Some(("--partial", value)) => partial_path = Some(value.to_string()),
+            Some(("--camera", value)) => projection = value.to_string(),
+            Some(("--stereo", value)) => stereo = Some(value.to_string()),
+            Some(("--physical", value)) => physical = Some(parse_physical(value)),
+            _ => exit_with(format!("unknown flag: {}", flag)),
+        }
+    }
+    if coordinator_address.is_some()
+        && (progressive
+            || options.time_budget.is_some()
+            || options.snapshot_passes.is_some()
+            || options.snapshot_interval.is_some()
+            || options.checkpoint_interval.is_some()
+            || checkpoint_path.is_some()
+            || resume_path.is_some())
+    {
+        exit_with("--coordinator takes all samples at once, so it cannot be combined with --time, --progressive, snapshots or checkpoints".to_string());
+    }
+    if options.time_budget.is_some() {
+        progressive = true;
+    }
+    let samples_per_pixel = samples_per_pixel.unwrap_or(match options.time_budget {
+        Some(_) => u32::MAX,
+        None => SAMPLES_PER_PIXEL,
+    });
+    let mut args = args.into_iter();
+    let integrator = match args.next() {
+        Some(arg) => parse(&arg),
+        None => Integrator::PathTracer,
+    };
+    let requested: Vec<Aov> = args.map(|arg| parse(&arg)).collect();
+    let mut aovs = requested.clone();
+    if denoise {
+        for guide in [Aov::Albedo, Aov::Normal, Aov::Depth] {
+            if !aovs.contains(&guide) {
+                aovs.push(guide);
+            }
+        }
+    }
 
     // Camera
-    let aspect_ratio = IMAGE_WIDTH as f64 / IMAGE_HEIGHT as f64;
+    let (kind, parameter) = match projection.split_once(':') {
+        Some((kind, parameter)) => (kind, Some(parameter)),
+        None => (projection.as_str(), None),
+    };
+    let image_height = match kind {
+        "equirectangular" => IMAGE_WIDTH / 2,
+        _ => IMAGE_HEIGHT,
+    };
+    let aspect_ratio = IMAGE_WIDTH as f64 / image_height as f64;
 
     let look_from = Point3::new(13.0, 2.0, 3.0);
     let look_at = Point3::new(0.0, 0.0, 0.0);
@@ -30,68 +171,411 @@ fn main() {
     let dist_to_focus = 10.0;
     let aperture = 0.1;
 
-    let cam = Camera::new(
-        look_from,
-        look_at,
-        vup,
-        20.0,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-    );
+    if physical.is_some() && (kind != "perspective" || parameter.is_some()) {
+        exit_with("a physical camera is a perspective camera".to_string());
+    }
+    let cam: Camera = match kind {
+        "perspective" => match &physical {
+            Some(physical) => physical.camera(look_from, look_at, vup, aspect_ratio, dist_to_focus),
+            None => Camera::new(
+                look_from,
+                look_at,
+                vup,
+                parameter.map_or(20.0, parse),
+                aspect_ratio,
+                aperture,
+                dist_to_focus,
+            ),
+        },
+        "orthographic" => {
+            let height = parameter.map_or(5.0, parse);
+            Orthographic::new(look_from, look_at, vup, height, aspect_ratio).into()
+        }
+        "fisheye" => {
+            let fov = parameter.map_or(180.0, parse);
+            Fisheye::new(look_from, look_at, vup, fov, aspect_ratio).into()
+        }
+        "equirectangular" => Equirectangular::new(look_from, look_at, vup).into(),
+        _ => exit_with(format!("unknown camera: {}", kind)),
+    };
+    let mut stereo_layout = None;
+    let cameras = match &stereo {
+        Some(value) => {
+            let mut parts = value.split(':');
+            stereo_layout = Some(parse::<StereoLayout>(parts.next().unwrap()));
+            let interaxial = parts.next().map_or(0.3, parse);
+            let convergence = parts.next().map_or(dist_to_focus, parse);
+            if convergence.is_nan() || convergence <= 0.0 {
+                exit_with(format!("convergence must be positive: {}", convergence));
+            }
+            if checkpoint_path.is_some()
+                || resume_path.is_some()
+                || coordinator_address.is_some()
+                || worker_address.is_some()
+            {
+                exit_with("stereo cannot be checkpointed or distributed".to_string());
+            }
+            let eyes = [Eye::Left, Eye::Right].map(|eye| cam.for_eye(eye, interaxial, convergence));
+            let [Some(left), Some(right)] = eyes else {
+                exit_with("stereo needs a perspective or equirectangular camera".to_string());
+            };
+            vec![left, right]
+        }
+        None => vec![cam],
+    };
 
-    // Render
-    let progress_counter = AtomicI32::new(0);
-    print_progress(IMAGE_HEIGHT);
-
-    let render: Vec<Color> = (0..IMAGE_HEIGHT)
-        .into_par_iter()
-        .rev()
-        .flat_map(|j| {
-            let mut rng = SmallRng::seed_from_u64(j as u64);
-            let mut scanline = Vec::with_capacity(IMAGE_WIDTH as usize);
-            for i in 0..IMAGE_WIDTH {
-                let mut pixel_color = Color::default();
-                for _s in 0..SAMPLES_PER_PIXEL {
-                    let u = (f64::from(i) + random_f64(&mut rng)) / f64::from(IMAGE_WIDTH);
-                    let v = (f64::from(j) + random_f64(&mut rng)) / f64::from(IMAGE_HEIGHT);
-                    let r = cam.get_ray(&mut rng, u, v);
-                    pixel_color += ray_color(&mut rng, r, world.as_ref(), MAX_DEPTH);
+    let renderer = Renderer::new(IMAGE_WIDTH, image_height)
+        .with_samples(samples_per_pixel)
+        .with_max_depth(MAX_DEPTH)
+        .with_integrator(integrator)
+        .with_aovs(&aovs)
+        .with_filter(filter)
+        .with_tiles(tile_size, tile_order)
+        .with_seed(sample_seed)
+        .with_exposure(physical.map_or(1.0, |physical| physical.exposure()));
+
+    // A resumed render rebuilds the world from the seed saved with the checkpoint, and a
+    // worker from the one its coordinator sends.
+    let worker = worker_address.as_ref().map(|address| {
+        Worker::connect(address)
+            .unwrap_or_else(|e| exit_with(format!("cannot connect to {}: {}", address, e)))
+    });
+    let (seed, resume) = match (&worker, &resume_path) {
+        (Some(worker), _) => (worker.seed, None),
+        (None, Some(path)) => {
+            let (seed, checkpoint) = read_checkpoint(path, &renderer)
+                .unwrap_or_else(|e| exit_with(format!("cannot resume from {}: {}", path, e)));
+            if checkpoint.camera != fingerprint(&cam) {
+                exit_with(format!("{} was rendered through another camera", path));
+            }
+            (seed, Some(checkpoint))
+        }
+        (None, None) => (
+            world_seed.unwrap_or_else(|| UNIX_EPOCH.elapsed().unwrap().as_secs()),
+            None,
+        ),
+    };
+    eprintln!("World seed: {}", seed);
+    let setup_start = Instant::now();
+    let scene = Scene::new(random_scene(seed));
+    let mut phases = vec![("Scene setup", setup_start.elapsed())];
+    if let (Some(worker), Some(address)) = (worker, worker_address) {
+        // One connection per core, each rendering a tile at a time.
+        let cores = thread::available_parallelism().map_or(1, |n| n.get());
+        let rendered: usize = thread::scope(|s| {
+            let (renderer, scene, cam) = (&renderer, &scene, &cam);
+            let others = (1..cores).filter_map(|_| Worker::connect(&address).ok());
+            let threads: Vec<_> = std::iter::once(worker)
+                .chain(others)
+                .map(|worker| s.spawn(move || worker.run(renderer, scene, cam)))
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| {
+                    thread
+                        .join()
+                        .unwrap()
+                        .unwrap_or_else(|e| exit_with(format!("worker stopped: {}", e)))
+                })
+                .sum()
+        });
+        eprintln!("Rendered {} tiles.", rendered);
+        return;
+    }
+
+    let checkpoint_path = checkpoint_path.or(resume_path);
+    if checkpoint_path.is_some() {
+        progressive = true;
+        options.checkpoint_interval = options
+            .checkpoint_interval
+            .or(Some(Duration::from_secs(60)));
+    }
+    let render_start = Instant::now();
+    let tile_count = renderer.tiles().len() * cameras.len();
+    // Tiles finished so far, and the rays traced for them.
+    let finished = Mutex::new((0, 0));
+    let tile_finished = |film: &Film| {
+        let mut finished = finished.lock().unwrap();
+        finished.0 += 1;
+        finished.1 += film.stats.all_rays();
+        print_progress(
+            render_start,
+            finished.0 as f64 / tile_count as f64,
+            finished.1,
+        );
+    };
+    // One frame, or one for each eye.
+    let mut frames: Vec<Frame> = if let Some(address) = coordinator_address {
+        let listener = TcpListener::bind(&address)
+            .unwrap_or_else(|e| exit_with(format!("cannot listen on {}: {}", address, e)));
+        eprintln!("Waiting for workers on {}", address);
+        let frame = coordinate(&renderer, &cam, &listener, seed, tile_timeout, |_, film| {
+            tile_finished(film)
+        })
+        .unwrap_or_else(|e| exit_with(format!("coordinator stopped: {}", e)));
+        vec![frame]
+    } else if !progressive {
+        cameras
+            .iter()
+            .map(|cam| renderer.render(&scene, cam, |_, film| tile_finished(film)))
+            .collect()
+    } else {
+        // Ctrl-C stops the render, which is then written out as usual.
+        let stop = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(SIGINT, Arc::clone(&stop)).unwrap();
+        // Passes rendered before resuming do not count towards the pace.
+        let (first_pass, first_rays) = resume.as_ref().map_or((0, 0), |checkpoint| {
+            (checkpoint.passes, checkpoint.film.stats.all_rays())
+        });
+        let on_progress = |progress: Progress| match progress {
+            Progress::Pass(passes, stats) => {
+                let done = match options.time_budget {
+                    Some(budget) if samples_per_pixel == u32::MAX => {
+                        render_start.elapsed().as_secs_f64() / budget.as_secs_f64()
+                    }
+                    _ => f64::from(passes - first_pass) / f64::from(samples_per_pixel - first_pass),
+                };
+                print_progress(render_start, done.min(1.0), stats.all_rays() - first_rays);
+            }
+            Progress::Snapshot(snapshot) => {
+                if let Err(e) = write_snapshot(snapshot) {
+                    eprintln!("\ncannot write snapshot.ppm: {}", e);
                 }
-                scanline.push(pixel_color);
             }
-            let finished = progress_counter.fetch_add(1, Ordering::AcqRel) + 1;
-            print_progress(IMAGE_HEIGHT - finished);
-            scanline
+            Progress::Checkpoint(checkpoint) => {
+                // Losing a checkpoint is no reason to lose the render too.
+                if let Some(path) = &checkpoint_path {
+                    if let Err(e) = write_checkpoint(path, seed, checkpoint) {
+                        eprintln!("\ncannot write checkpoint {}: {}", path, e);
+                    }
+                }
+            }
+        };
+        match stereo_layout {
+            Some(layout) => {
+                let eyes = [&cameras[0], &cameras[1]];
+                renderer
+                    .render_stereo_progressive(&scene, eyes, layout, &options, &stop, on_progress)
+                    .to_vec()
+            }
+            None => vec![renderer.render_progressive(
+                &scene,
+                &cameras[0],
+                &options,
+                &stop,
+                resume,
+                on_progress,
+            )],
+        }
+    };
+    phases.push(("Rendering", render_start.elapsed()));
+
+    // Write
+    let pack = |frames: &mut Vec<Frame>| match stereo_layout {
+        Some(layout) => Frame::stereo(&frames[0], &frames[1], layout),
+        None => frames.pop().unwrap(),
+    };
+    // Each eye is denoised on its own, so that the filter does not blend them where they meet.
+    let denoise_start = Instant::now();
+    let denoised = denoise.then(|| {
+        frames
+            .iter()
+            .map(|frame| Frame {
+                beauty: Denoiser::default().denoise(frame).unwrap(),
+                ..frame.clone()
+            })
+            .collect()
+    });
+    let frame = pack(&mut frames);
+    let image = match denoised {
+        Some(mut denoised) => {
+            phases.push(("Denoising", denoise_start.elapsed()));
+            pack(&mut denoised).beauty
+        }
+        None => frame.beauty.clone(),
+    };
+    let write_start = Instant::now();
+    let stats = frame.stats;
+    eprintln!("\nSamples per pixel: {}", frame.samples_per_pixel);
+    print_image(&frame, image);
+    for aov in requested {
+        let path = format!("{}.pfm", aov.name());
+        let file = BufWriter::new(File::create(&path).unwrap());
+        frame.write_pfm(frame.layer(aov).unwrap(), file).unwrap();
+        eprintln!("\nWrote {}", path);
+    }
+    if let Some(path) = partial_path {
+        let partial = Partial {
+            scene_hash: render_fingerprint(&scene, &cameras, &renderer),
+            frame,
+        };
+        write_partial(&path, &partial);
+        eprintln!("\nWrote {}", path);
+    }
+    phases.push(("Writing", write_start.elapsed()));
+
+    eprintln!("\n{}", stats);
+    for (phase, time) in phases {
+        eprintln!("{:<21}{:.2}s", format!("{}:", phase), time.as_secs_f64());
+    }
+    eprintln!("\nDone.");
+}
+
+/// `badtracing merge`: adds up the samples of partial renders.
+fn merge_renders<I: Iterator<Item = String>>(args: I) {
+    let (flags, paths): (Vec<String>, Vec<String>) = args.partition(|arg| arg.starts_with("--"));
+    let mut partial_path = None;
+    for flag in flags {
+        match flag.split_once('=') {
+            Some(("--partial", value)) => partial_path = Some(value.to_string()),
+            _ => exit_with(format!("unknown flag: {}", flag)),
+        }
+    }
+    let partials: Vec<Partial> = paths
+        .iter()
+        .map(|path| {
+            File::open(path)
+                .and_then(|file| Partial::read_from(BufReader::new(file)))
+                .unwrap_or_else(|e| exit_with(format!("cannot read {}: {}", path, e)))
         })
         .collect();
+    let merged = merge(&partials).unwrap_or_else(|e| exit_with(e));
+    eprintln!(
+        "Merged {} renders into {} samples per pixel.",
+        partials.len(),
+        merged.frame.samples_per_pixel
+    );
+    print_image(&merged.frame, merged.frame.beauty.clone());
+    if let Some(path) = partial_path {
+        write_partial(&path, &merged);
+        eprintln!("Wrote {}", path);
+    }
+}
 
-    // Write
+/// Writes a PPM image of the frame to stdout, noting its samples per pixel in the header.
+fn print_image(frame: &Frame, pixels: Vec<Color>) {
     println!("P3");
-    println!("{} {}", IMAGE_WIDTH, IMAGE_HEIGHT);
+    println!("# samples per pixel: {}", frame.samples_per_pixel);
+    println!("{} {}", frame.width, frame.height);
     println!("255");
-    render
+    pixels
         .into_iter()
-        .for_each(|pixel_color| write_color(pixel_color, SAMPLES_PER_PIXEL));
+        .for_each(|pixel_color| write_color(pixel_color, 1));
+}
 
-    eprintln!("\nDone.");
+fn write_partial(path: &str, partial: &Partial) {
+    File::create(path)
+        .and_then(|file| partial.write_to(BufWriter::new(file)))
+        .unwrap_or_else(|e| exit_with(format!("cannot write {}: {}", path, e)));
 }
 
-fn print_progress<D: Display>(remaining: D) {
-    eprint!("\rScanlines remaining: {} ", remaining);
+/// Parses `focal[:f-number[:shutter[:iso]]]`, keeping the defaults for what is left out.
+fn parse_physical(value: &str) -> PhysicalCamera {
+    let settings: Vec<f64> = value
+        .split(':')
+        .map(|part| match part.split_once('/') {
+            Some((numerator, denominator)) => parse::<f64>(numerator) / parse::<f64>(denominator),
+            None => parse(part),
+        })
+        .collect();
+    let default = PhysicalCamera::default();
+    let setting = |i: usize, default: f64| settings.get(i).copied().unwrap_or(default);
+    if settings.len() > 4 {
+        exit_with(format!("too many physical camera settings: {}", value));
+    }
+    PhysicalCamera {
+        focal_length: setting(0, default.focal_length),
+        f_number: setting(1, default.f_number),
+        shutter_time: setting(2, default.shutter_time),
+        iso: setting(3, default.iso),
+        ..default
+    }
 }
 
-fn random_scene() -> Vec<Object> {
+fn parse<T: FromStr>(value: &str) -> T
+where
+    T::Err: Display,
+{
+    value
+        .parse()
+        .unwrap_or_else(|e| exit_with(format!("invalid value {:?}: {}", value, e)))
+}
+
+fn parse_seconds(value: &str) -> Duration {
+    Duration::try_from_secs_f64(parse(value))
+        .unwrap_or_else(|e| exit_with(format!("invalid duration {:?}: {}", value, e)))
+}
+
+fn exit_with(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(2);
+}
+
+/// Prints how much of the render is `done`, from 0 to 1, how long it took so far and how much
+/// longer it should take, and how many of the `rays` traced so far went by per second.
+fn print_progress(start: Instant, done: f64, rays: u64) {
+    let elapsed = start.elapsed().as_secs_f64();
+    let eta = if done > 0.0 {
+        format_seconds(elapsed * (1.0 - done) / done)
+    } else {
+        "?".to_string()
+    };
+    eprint!(
+        "\r{:5.1}%  elapsed {}  ETA {}  {:.2} Mrays/s ",
+        100.0 * done,
+        format_seconds(elapsed),
+        eta,
+        rays as f64 / elapsed.max(1e-9) / 1e6
+    );
+}
+
+fn format_seconds(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Writes the world seed on a line of its own, then the checkpoint. The file is replaced
+/// only once it is complete, so being interrupted never leaves a broken checkpoint behind.
+fn write_checkpoint(path: &str, seed: u64, checkpoint: &Checkpoint) -> io::Result<()> {
+    let partial = format!("{}.partial", path);
+    let mut file = BufWriter::new(File::create(&partial)?);
+    writeln!(file, "{}", seed)?;
+    checkpoint.write_to(&mut file)?;
+    file.into_inner()?.sync_all()?;
+    std::fs::rename(partial, path)
+}
+
+fn write_snapshot(snapshot: &Frame) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create("snapshot.ppm")?);
+    snapshot.write_ppm(&snapshot.beauty, &mut file)?;
+    file.flush()
+}
+
+fn read_checkpoint(path: &str, renderer: &Renderer) -> io::Result<(u64, Checkpoint)> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut line = String::new();
+    file.read_line(&mut line)?;
+    let seed = line
+        .trim()
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok((seed, Checkpoint::read_from(file, renderer)?))
+}
+
+fn random_scene(seed: u64) -> Vec<Object> {
     let mut world = Vec::new();
 
     let ground_material = Lambertian {
         albedo: Color::new(0.5, 0.5, 0.5),
     }
     .into();
-    world.push(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground_material).into());
+    world.push(Plane::new(Point3::default(), Vec3::new(0.0, 1.0, 0.0), ground_material).into());
 
-    let seed = UNIX_EPOCH.elapsed().unwrap().as_secs();
-    eprintln!("World seed: {}", seed);
     let mut rng = SmallRng::seed_from_u64(seed);
     for a in -11..11 {
         for b in -11..11 {
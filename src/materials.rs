@@ -2,19 +2,57 @@ use crate::ray::Ray;
 use crate::{random_f64, Color, HitRecord};
 
 use crate::vec3::Vec3;
-use rand::Rng;
+use rand::{Rng, RngCore};
+use std::fmt::Debug;
+use std::sync::Arc;
 
 #[enum_delegate::register]
 pub trait MaterialProperties {
-    fn scatter<R: Rng>(&self, rng: &mut R, r: Ray, rec: HitRecord) -> Option<(Color, Ray)>;
+    fn scatter<R: Rng>(&self, rng: &mut R, r: Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::default()
+    }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 #[enum_delegate::implement(MaterialProperties)]
 pub enum Material {
     Lambertian(Lambertian),
     Metal(Metal),
     Dielectric(Dielectric),
+    DiffuseLight(DiffuseLight),
+    Custom(Custom),
+}
+
+/// Materials defined outside this crate. Unlike `MaterialProperties` this trait can be used
+/// as a trait object, at the cost of a dynamically dispatched random number generator.
+pub trait CustomMaterial: Debug + Send + Sync {
+    fn scatter(&self, rng: &mut dyn RngCore, r: Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::default()
+    }
+}
+
+/// Shares a user-defined material between the objects made of it and their hit records.
+#[derive(Clone, Debug)]
+pub struct Custom(pub Arc<dyn CustomMaterial + Send + Sync>);
+
+impl Custom {
+    pub fn new<M: CustomMaterial + 'static>(material: M) -> Self {
+        Custom(Arc::new(material))
+    }
+}
+
+impl MaterialProperties for Custom {
+    fn scatter<R: Rng>(&self, rng: &mut R, r: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        self.0.scatter(rng, r, rec)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.0.emitted(rec)
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -23,7 +61,7 @@ pub struct Lambertian {
 }
 
 impl MaterialProperties for Lambertian {
-    fn scatter<R: Rng>(&self, rng: &mut R, _r: Ray, rec: HitRecord) -> Option<(Color, Ray)> {
+    fn scatter<R: Rng>(&self, rng: &mut R, r: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
         let mut scatter_direction = rec.normal + Vec3::random_unit_vector(rng);
 
         // Catch degenerate scatter direction
@@ -31,7 +69,7 @@ impl MaterialProperties for Lambertian {
             scatter_direction = rec.normal;
         }
 
-        let scattered = Ray::new(rec.p, scatter_direction);
+        let scattered = Ray::new(rec.p, scatter_direction, r.time);
         Some((self.albedo, scattered))
     }
 }
@@ -43,11 +81,12 @@ pub struct Metal {
 }
 
 impl MaterialProperties for Metal {
-    fn scatter<R: Rng>(&self, rng: &mut R, r: Ray, rec: HitRecord) -> Option<(Color, Ray)> {
+    fn scatter<R: Rng>(&self, rng: &mut R, r: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
         let reflected = r.dir.unit_vector().reflect(rec.normal);
         let scattered = Ray::new(
             rec.p,
             reflected + self.fuzz * Vec3::random_in_unit_sphere(rng),
+            r.time,
         );
         Some((self.albedo, scattered))
     }
@@ -68,7 +107,7 @@ impl Dielectric {
 }
 
 impl MaterialProperties for Dielectric {
-    fn scatter<R: Rng>(&self, rng: &mut R, r: Ray, rec: HitRecord) -> Option<(Color, Ray)> {
+    fn scatter<R: Rng>(&self, rng: &mut R, r: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
         let refraction_ratio = if rec.front_face {
             1.0 / self.ir
         } else {
@@ -86,7 +125,28 @@ impl MaterialProperties for Dielectric {
                 unit_direction.refract(rec.normal, refraction_ratio)
             };
 
-        let scattered = Ray::new(rec.p, direction);
+        let scattered = Ray::new(rec.p, direction, r.time);
         Some((Color::new(1.0, 1.0, 1.0), scattered))
     }
 }
+
+/// Emits light from the front face and reflects nothing. Added with `Scene::add_area_light`,
+/// it is also sampled directly, which lights diffuse surfaces with much less noise.
+#[derive(Copy, Clone, Debug)]
+pub struct DiffuseLight {
+    pub emit: Color,
+}
+
+impl MaterialProperties for DiffuseLight {
+    fn scatter<R: Rng>(&self, _rng: &mut R, _r: Ray, _rec: &HitRecord) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        if rec.front_face {
+            self.emit
+        } else {
+            Color::default()
+        }
+    }
+}
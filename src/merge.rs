@@ -0,0 +1,275 @@
+use crate::camera::Camera;
+use crate::film::{invalid_data, read_f64, read_u64, write_f64, write_u64};
+use crate::render::{Frame, Renderer};
+use crate::scene::Scene;
+use crate::stats::Stats;
+use crate::tiles::TileOrder;
+use crate::Color;
+
+use std::fmt::Debug;
+use std::io::{self, Read, Write};
+
+const MAGIC: &[u8; 8] = b"BTPART02";
+
+/// A frame rendered on its own, to be merged with others of the same scene that took
+/// different samples.
+#[derive(Debug, Clone)]
+pub struct Partial {
+    /// `render_fingerprint` of what was rendered; only partials of the same render are merged.
+    pub scene_hash: u64,
+    pub frame: Frame,
+}
+
+impl Partial {
+    /// Writes the pixels as exact floats, along with the number of samples, the statistics and
+    /// the scene hash.
+    pub fn write_to<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let frame = &self.frame;
+        out.write_all(MAGIC)?;
+        write_u64(&mut out, self.scene_hash)?;
+        for n in [frame.width, frame.height, frame.layers.len()] {
+            write_u64(&mut out, n as u64)?;
+        }
+        write_u64(&mut out, frame.samples_per_pixel.into())?;
+        frame.stats.write_to(&mut out)?;
+        write_pixels(&mut out, &frame.beauty)?;
+        for (aov, pixels) in &frame.layers {
+            let name = aov.name();
+            write_u64(&mut out, name.len() as u64)?;
+            out.write_all(name.as_bytes())?;
+            write_pixels(&mut out, pixels)?;
+        }
+        out.flush()
+    }
+
+    pub fn read_from<R: Read>(mut input: R) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a partial render"));
+        }
+        let scene_hash = read_u64(&mut input)?;
+        let width = read_u64(&mut input)? as usize;
+        let height = read_u64(&mut input)? as usize;
+        let layer_count = read_u64(&mut input)?;
+        let samples_per_pixel = read_u64(&mut input)?
+            .try_into()
+            .map_err(|_| invalid_data("too many samples"))?;
+        let stats = Stats::read_from(&mut input)?;
+        let beauty = read_pixels(&mut input, width * height)?;
+        let mut layers = Vec::new();
+        for _ in 0..layer_count {
+            let length = read_u64(&mut input)?;
+            let mut name = String::new();
+            input.by_ref().take(length).read_to_string(&mut name)?;
+            let aov = name.parse().map_err(|e: String| invalid_data(&e))?;
+            layers.push((aov, read_pixels(&mut input, width * height)?));
+        }
+        Ok(Partial {
+            scene_hash,
+            frame: Frame {
+                width,
+                height,
+                samples_per_pixel,
+                beauty,
+                layers,
+                stats,
+            },
+        })
+    }
+}
+
+fn write_pixels<W: Write>(out: &mut W, pixels: &[Color]) -> io::Result<()> {
+    for c in pixels {
+        for v in [c.x, c.y, c.z] {
+            write_f64(out, v)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_pixels<R: Read>(input: &mut R, count: usize) -> io::Result<Vec<Color>> {
+    (0..count)
+        .map(|_| {
+            Ok(Color::new(
+                read_f64(input)?,
+                read_f64(input)?,
+                read_f64(input)?,
+            ))
+        })
+        .collect()
+}
+
+/// Combines renders of the same scene into one with all of their samples, weighting each
+/// pixel by how many samples went into it. The renders must have the same resolution, scene
+/// hash and AOVs.
+pub fn merge(partials: &[Partial]) -> Result<Partial, String> {
+    let (first, rest) = partials.split_first().ok_or("nothing to merge")?;
+    let aovs = |p: &Partial| {
+        p.frame
+            .layers
+            .iter()
+            .map(|(aov, _)| *aov)
+            .collect::<Vec<_>>()
+    };
+    for (i, p) in rest.iter().enumerate() {
+        let i = i + 1;
+        if (p.frame.width, p.frame.height) != (first.frame.width, first.frame.height) {
+            return Err(format!(
+                "render {} is {}x{}, but render 0 is {}x{}",
+                i, p.frame.width, p.frame.height, first.frame.width, first.frame.height
+            ));
+        }
+        if p.scene_hash != first.scene_hash {
+            return Err(format!(
+                "render {} is of a different scene than render 0",
+                i
+            ));
+        }
+        if aovs(p) != aovs(first) {
+            return Err(format!("render {} has different AOVs than render 0", i));
+        }
+    }
+
+    let samples: u32 = partials.iter().map(|p| p.frame.samples_per_pixel).sum();
+    let mut stats = Stats::default();
+    for p in partials {
+        stats += p.frame.stats;
+    }
+    let combine = |pixels: &dyn Fn(&Partial) -> &[Color]| {
+        let mut sum = vec![Color::default(); first.frame.beauty.len()];
+        for p in partials {
+            let weight = f64::from(p.frame.samples_per_pixel) / f64::from(samples.max(1));
+            for (s, c) in sum.iter_mut().zip(pixels(p)) {
+                *s += weight * *c;
+            }
+        }
+        sum
+    };
+    let layers = first
+        .frame
+        .layers
+        .iter()
+        .enumerate()
+        .map(|(l, (aov, _))| (*aov, combine(&|p| &p.frame.layers[l].1)))
+        .collect();
+    Ok(Partial {
+        scene_hash: first.scene_hash,
+        frame: Frame {
+            samples_per_pixel: samples,
+            beauty: combine(&|p| &p.frame.beauty),
+            layers,
+            stats,
+            ..first.frame.clone()
+        },
+    })
+}
+
+/// `fingerprint` of everything that decides what a render converges to: the scene, the
+/// cameras and the renderer settings, except for the seed, number of samples and tiles, which
+/// only change which samples are taken. Partials are only merged if this matches.
+pub fn render_fingerprint(scene: &Scene, cameras: &[Camera], renderer: &Renderer) -> u64 {
+    let settings = Renderer {
+        seed: 0,
+        tile_size: 0,
+        tile_order: TileOrder::default(),
+        ..renderer.clone()
+    }
+    .settings();
+    fingerprint(&(scene, cameras, settings))
+}
+
+/// Hash of the `Debug` output of `value`, such as a scene and its camera. It does not change
+/// between builds or machines. Custom objects and materials all look the same to it.
+pub fn fingerprint<T: Debug + ?Sized>(value: &T) -> u64 {
+    // 64-bit FNV-1a.
+    format!("{:?}", value)
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::materials::Lambertian;
+    use crate::objects::Sphere;
+    use crate::render::{Aov, Integrator};
+    use crate::vec3::Vec3;
+    use crate::Point3;
+
+    #[test]
+    fn test_merge_weights_by_samples() {
+        let scene = Scene::new(vec![Sphere::new(
+            Point3::default(),
+            1.0,
+            Lambertian {
+                albedo: Color::new(0.5, 0.3, 0.1),
+            }
+            .into(),
+        )
+        .into()]);
+        let camera = Camera::new(
+            Point3::new(0.0, 0.0, 4.0),
+            Point3::default(),
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+            1.0,
+            0.0,
+            4.0,
+        );
+        let renderer = Renderer::new(6, 6).with_aovs(&[Aov::Depth]);
+        let scene_hash = render_fingerprint(&scene, &[camera], &renderer);
+        // Renders that differ in more than their samples do not get merged.
+        let seeded = renderer.clone().with_seed(3).with_samples(9);
+        assert_eq!(render_fingerprint(&scene, &[camera], &seeded), scene_hash);
+        let occlusion = renderer
+            .clone()
+            .with_integrator(Integrator::AmbientOcclusion { radius: 1.0 });
+        assert_ne!(
+            render_fingerprint(&scene, &[camera], &occlusion),
+            scene_hash
+        );
+        let exposed = renderer.clone().with_exposure(2.0);
+        assert_ne!(render_fingerprint(&scene, &[camera], &exposed), scene_hash);
+        let render = |seed, samples| Partial {
+            scene_hash,
+            frame: renderer
+                .clone()
+                .with_seed(seed)
+                .with_samples(samples)
+                .render(&scene, &camera, |_, _| {}),
+        };
+        let (a, b) = (render(1, 1), render(2, 3));
+        assert_ne!(a.frame.beauty, b.frame.beauty);
+
+        let mut bytes = Vec::new();
+        b.write_to(&mut bytes).unwrap();
+        let b = Partial::read_from(bytes.as_slice()).unwrap();
+        let merged = merge(&[a.clone(), b.clone()]).unwrap();
+        assert_eq!(merged.frame.samples_per_pixel, 4);
+        if cfg!(feature = "stats") {
+            assert_eq!(merged.frame.stats.camera_rays, 6 * 6 * 4);
+        }
+        for (i, m) in merged.frame.beauty.iter().enumerate() {
+            let expected = 0.25 * a.frame.beauty[i] + 0.75 * b.frame.beauty[i];
+            assert!((*m - expected).length() < 1e-12);
+        }
+        assert_eq!(merged.frame.layers[0].0, Aov::Depth);
+
+        let other = Partial {
+            scene_hash: scene_hash + 1,
+            ..b.clone()
+        };
+        assert!(merge(&[a.clone(), other]).is_err());
+        let smaller = Partial {
+            scene_hash,
+            frame: Renderer::new(4, 6)
+                .with_aovs(&[Aov::Depth])
+                .render(&scene, &camera, |_, _| {}),
+        };
+        assert!(merge(&[a, smaller]).is_err());
+        assert!(merge(&[]).is_err());
+    }
+}
@@ -1,39 +1,181 @@
+use crate::aabb::Aabb;
+use crate::csg::{Csg, Solid, Span};
 use crate::materials::Material;
+use crate::primitives::{Capsule, Cone, Cylinder, Disk, Plane, Torus};
 use crate::ray::Ray;
-use crate::{HitRecord, Point3, UnitVec3};
+use crate::sdf::SdfObject;
+use crate::stats;
+use crate::{random_f64, HitRecord, Point3, UnitVec3};
 
-use crate::vec3::Vec3;
+use crate::vec3::{Quat, Transform, Vec3};
+use rand::Rng;
+use std::f64::consts::PI;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
 
 #[enum_delegate::register]
 pub trait Hittable {
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+
+    /// Box enclosing the object at every point in time, or `None` if it is unbounded.
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    /// Whether anything is hit between `t_min` and `t_max`. Shadow rays only need this, so
+    /// objects can stop at the first hit and skip building the `HitRecord`.
+    fn occluded(&self, r: Ray, t_min: f64, t_max: f64) -> bool {
+        self.hit(r, t_min, t_max).is_some()
+    }
 }
 
-impl Hittable for &[Object] {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let mut current_t = t_max;
-        let mut result = None;
-        for h in self.iter() {
-            if let Some(hit) = h.hit(r, t_min, current_t) {
-                if hit.t < current_t {
-                    current_t = hit.t;
-                    result = Some(hit);
-                }
+/// The nearest hit among `objects`, with the index of the object hit.
+pub(crate) fn hit_nearest(
+    objects: &[Object],
+    r: Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(usize, HitRecord)> {
+    stats::record(|s| s.intersection_tests += objects.len() as u64);
+    let mut current_t = t_max;
+    let mut result = None;
+    for (index, h) in objects.iter().enumerate() {
+        if let Some(hit) = h.hit(r, t_min, current_t) {
+            if hit.t < current_t {
+                current_t = hit.t;
+                result = Some((index, hit));
             }
         }
-        result
+    }
+    result
+}
+
+impl Hittable for &[Object] {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        hit_nearest(self, r, t_min, t_max).map(|(_, rec)| rec)
+    }
+
+    fn occluded(&self, r: Ray, t_min: f64, t_max: f64) -> bool {
+        let blocker = self.iter().position(|h| h.occluded(r, t_min, t_max));
+        let tested = blocker.map_or(self.len(), |i| i + 1);
+        stats::record(|s| s.intersection_tests += tested as u64);
+        blocker.is_some()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (first, rest) = self.split_first()?;
+        rest.iter().try_fold(first.bounding_box()?, |b, h| {
+            Some(b.surrounding(h.bounding_box()?))
+        })
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 #[enum_delegate::implement(Hittable)]
 pub enum Object {
     Sphere(Sphere),
-    Square(Square),
+    Quad(Quad),
     Cube(Cube),
+    Instance(Instance),
+    MovingSphere(MovingSphere),
+    MovingCube(MovingCube),
+    AnimatedInstance(AnimatedInstance),
+    Csg(Csg),
+    Sdf(SdfObject),
+    Plane(Plane),
+    Disk(Disk),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Capsule(Capsule),
+    Torus(Torus),
+    Custom(CustomObject),
 }
 
-#[derive(Debug, Copy, Clone)]
+/// Wraps a shape defined outside this crate so that it can be part of the world.
+#[derive(Clone)]
+pub struct CustomObject(pub Arc<dyn Hittable + Send + Sync>);
+
+impl CustomObject {
+    pub fn new<H: Hittable + Send + Sync + 'static>(hittable: H) -> Self {
+        CustomObject(Arc::new(hittable))
+    }
+}
+
+impl Debug for CustomObject {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CustomObject")
+    }
+}
+
+impl Hittable for CustomObject {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.0.hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.0.bounding_box()
+    }
+
+    fn occluded(&self, r: Ray, t_min: f64, t_max: f64) -> bool {
+        self.0.occluded(r, t_min, t_max)
+    }
+}
+
+/// Intersects a ray with an object placed in the world by `transform`: `hit_local` is called
+/// with the ray in object space and its hit is mapped back to world space.
+pub(crate) fn hit_transformed<F>(transform: Transform, r: Ray, hit_local: F) -> Option<HitRecord>
+where
+    F: FnOnce(Ray) -> Option<HitRecord>,
+{
+    let rec = hit_local(to_object_space(transform, r))?;
+    Some(to_world_space(transform, r, rec))
+}
+
+fn to_object_space(transform: Transform, r: Ray) -> Ray {
+    let inv = transform.inverse();
+    // The direction is not renormalized, so `t` means the same in both spaces.
+    Ray::new(inv.point(r.orig), inv.vector(r.dir), r.time)
+}
+
+fn to_world_space(transform: Transform, r: Ray, rec: HitRecord) -> HitRecord {
+    let outward_normal = if rec.front_face {
+        rec.normal
+    } else {
+        -rec.normal
+    };
+    let normal = transform.normal(outward_normal).unit_vector();
+    HitRecord::new(r.at(rec.t), r.dir, rec.t, normal, rec.material).with_uv(rec.u, rec.v)
+}
+
+/// A shared object placed in the world by a transform, so one model can be reused many times.
+#[derive(Debug, Clone)]
+pub struct Instance {
+    object: Arc<Object>,
+    transform: Transform,
+}
+
+impl Instance {
+    pub fn new(object: Arc<Object>, transform: Transform) -> Self {
+        Self { object, transform }
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        hit_transformed(self.transform, r, |local| {
+            self.object.hit(local, t_min, t_max)
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.object.bounding_box()?.transform(self.transform))
+    }
+
+    fn occluded(&self, r: Ray, t_min: f64, t_max: f64) -> bool {
+        let local = to_object_space(self.transform, r);
+        self.object.occluded(local, t_min, t_max)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Sphere {
     center: Point3,
     radius: f64,
@@ -50,8 +192,8 @@ impl Sphere {
     }
 }
 
-impl Hittable for Sphere {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+impl Sphere {
+    fn root(&self, r: Ray, t_min: f64, t_max: f64) -> Option<f64> {
         let oc = r.orig - self.center;
         let a = r.dir.length_squared();
         let half_b = oc.dot(r.dir);
@@ -71,135 +213,708 @@ impl Hittable for Sphere {
                 return None;
             }
         }
+        Some(root)
+    }
+}
 
-        let t = root;
+impl Hittable for Sphere {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let t = self.root(r, t_min, t_max)?;
         let p = r.at(t);
         let outward_normal = (p - self.center) / self.radius;
+        let (u, v) = sphere_uv(outward_normal);
+
+        Some(HitRecord::new(p, r.dir, t, outward_normal, self.material.clone()).with_uv(u, v))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = self.radius.abs();
+        Some(Aabb::centered(self.center, Vec3::new(r, r, r)))
+    }
 
-        Some(HitRecord::new(p, r.dir, t, outward_normal, self.material))
+    fn occluded(&self, r: Ray, t_min: f64, t_max: f64) -> bool {
+        self.root(r, t_min, t_max).is_some()
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct Square {
-    center: Point3,
-    radius: f64,
+/// Longitude and latitude of a point on the unit sphere, with `v = 0` at the south pole.
+pub(crate) fn sphere_uv(p: UnitVec3) -> (f64, f64) {
+    let theta = (-p.y).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z).atan2(p.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+impl Solid for Sphere {
+    fn spans(&self, r: Ray) -> Vec<Span> {
+        let oc = r.orig - self.center;
+        let a = r.dir.length_squared();
+        let half_b = oc.dot(r.dir);
+        let c = oc.length_squared() - self.radius * self.radius;
+
+        let discriminant = half_b * half_b - a * c;
+        if discriminant <= 0.0 {
+            return Vec::new();
+        }
+        let sqrtd = discriminant.sqrt();
+        let record = |t: f64| {
+            let p = r.at(t);
+            let outward_normal = (p - self.center) / self.radius;
+            let (u, v) = sphere_uv(outward_normal);
+            HitRecord::new(p, r.dir, t, outward_normal, self.material.clone()).with_uv(u, v)
+        };
+        vec![Span {
+            enter: record((-half_b - sqrtd) / a),
+            exit: record((-half_b + sqrtd) / a),
+        }]
+    }
+}
+
+/// Parallelogram with a corner at `q` and edges `u` and `v`. The front face is the side its
+/// normal `u x v` points to.
+#[derive(Debug, Clone)]
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
     normal: UnitVec3,
-    orientation: UnitVec3,
+    w: Vec3,
+    area: f64,
     material: Material,
 }
 
-impl Hittable for Square {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = r.orig - self.center;
-        let d = r.dir.dot(self.normal);
-        if d.abs() < 1e-16 {
-            return None;
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, material: Material) -> Self {
+        let n = u.cross(v);
+        Self {
+            q,
+            u,
+            v,
+            normal: n.unit_vector(),
+            w: n / n.length_squared(),
+            area: n.length(),
+            material,
         }
-        let a = -oc.dot(self.normal);
+    }
 
-        let t = a / d;
-        if t < t_min || t > t_max {
+    /// Rectangle in the plane `z = k` facing +z.
+    pub fn xy_rect(x0: f64, x1: f64, y0: f64, y1: f64, k: f64, material: Material) -> Self {
+        Self::new(
+            Point3::new(x0, y0, k),
+            Vec3::new(x1 - x0, 0.0, 0.0),
+            Vec3::new(0.0, y1 - y0, 0.0),
+            material,
+        )
+    }
+
+    /// Rectangle in the plane `y = k` facing +y.
+    pub fn xz_rect(x0: f64, x1: f64, z0: f64, z1: f64, k: f64, material: Material) -> Self {
+        Self::new(
+            Point3::new(x0, k, z0),
+            Vec3::new(0.0, 0.0, z1 - z0),
+            Vec3::new(x1 - x0, 0.0, 0.0),
+            material,
+        )
+    }
+
+    /// Rectangle in the plane `x = k` facing +x.
+    pub fn yz_rect(y0: f64, y1: f64, z0: f64, z1: f64, k: f64, material: Material) -> Self {
+        Self::new(
+            Point3::new(k, y0, z0),
+            Vec3::new(0.0, y1 - y0, 0.0),
+            Vec3::new(0.0, 0.0, z1 - z0),
+            material,
+        )
+    }
+
+    pub fn with_material(self, material: Material) -> Self {
+        Self { material, ..self }
+    }
+
+    pub fn normal(&self) -> UnitVec3 {
+        self.normal
+    }
+
+    pub fn area(&self) -> f64 {
+        self.area
+    }
+
+    /// Uniformly distributed point on the quad.
+    pub fn random_point<R: Rng>(&self, rng: &mut R) -> Point3 {
+        self.q + random_f64(rng) * self.u + random_f64(rng) * self.v
+    }
+
+    /// Solid angle density of sampling `direction` from `origin` with `random_point`.
+    pub fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let r = Ray::new(origin, direction, 0.0);
+        match self.hit(r, 0.001, f64::INFINITY) {
+            Some(rec) => {
+                let dist_squared = rec.t * rec.t * direction.length_squared();
+                let cosine = (direction.dot(self.normal) / direction.length()).abs();
+                dist_squared / (cosine * self.area)
+            }
+            None => 0.0,
+        }
+    }
+}
+
+impl Quad {
+    /// Ray parameter of the hit and its coordinates along both edges.
+    fn intersect(&self, r: Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let denom = self.normal.dot(r.dir);
+        if denom.abs() < 1e-16 {
             return None;
         }
-        let p = r.at(t);
-        let op = p - self.center;
-        let outward_normal = self.normal;
-        if op.dot(self.orientation).abs() > self.radius {
+        let t = (self.q - r.orig).dot(self.normal) / denom;
+        if t < t_min || t > t_max {
             return None;
         }
-        if op.dot(self.orientation.cross(self.normal)).abs() > self.radius {
+        let p = r.at(t);
+        // Coordinates of the hit point along both edges.
+        let planar = p - self.q;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
             return None;
         }
+        Some((t, alpha, beta))
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (t, alpha, beta) = self.intersect(r, t_min, t_max)?;
+        let p = r.at(t);
+        Some(HitRecord::new(p, r.dir, t, self.normal, self.material.clone()).with_uv(alpha, beta))
+    }
+
+    fn occluded(&self, r: Ray, t_min: f64, t_max: f64) -> bool {
+        self.intersect(r, t_min, t_max).is_some()
+    }
 
-        Some(HitRecord::new(p, r.dir, t, outward_normal, self.material))
+    fn bounding_box(&self) -> Option<Aabb> {
+        let b = Aabb::new(self.q, self.q + self.u + self.v)
+            .surrounding(Aabb::new(self.q + self.u, self.q + self.v));
+        // Padded so that the box of an axis-aligned quad is not flat.
+        let pad = Vec3::new(1e-4, 1e-4, 1e-4);
+        Some(Aabb::new(b.min - pad, b.max + pad))
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+/// An oriented box: the axis-aligned box `[-half_extents, half_extents]` placed in the world
+/// by `transform`.
+#[derive(Debug, Clone)]
 pub struct Cube {
-    center: Point3,
-    radius: f64,
-    a: Vec3,
-    b: Vec3,
-    c: Vec3,
+    transform: Transform,
+    half_extents: Vec3,
     material: Material,
 }
 
 impl Cube {
     pub fn new(center: Point3, radius: f64, axis0: Vec3, axis1: Vec3, material: Material) -> Self {
+        let half_extents = Vec3::new(radius, radius, radius);
+        Self::cuboid(center, half_extents, axis0, axis1, material)
+    }
+
+    /// Box with its first two axes along `axis0` and (the part of `axis1` orthogonal to)
+    /// `axis1`, extending `half_extents` from the center along each axis.
+    pub fn cuboid(
+        center: Point3,
+        half_extents: Vec3,
+        axis0: Vec3,
+        axis1: Vec3,
+        material: Material,
+    ) -> Self {
         assert!(axis0.cross(axis1).length_squared() > 0.0);
         let a = axis0.unit_vector();
         let b = (axis1 - a * axis1.dot(a)).unit_vector();
         let c = a.cross(b);
 
         Self {
-            center,
-            radius,
-            a,
-            b,
-            c,
+            transform: Transform::translate(center) * Transform::from_basis(a, b, c),
+            half_extents,
             material,
         }
     }
-}
 
-impl Hittable for Cube {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        if (r.orig - self.center).length() > r.dir.length() * t_max + 2.0 * self.radius {
-            return None;
+    /// The box `[-1, 1]` on every axis, placed in the world by `transform`.
+    pub fn with_transform(transform: Transform, material: Material) -> Self {
+        Self {
+            transform,
+            half_extents: Vec3::new(1.0, 1.0, 1.0),
+            material,
         }
-        [
-            Square {
-                center: self.center + self.radius * self.a,
-                radius: self.radius,
-                normal: self.a,
-                orientation: self.b,
-                material: self.material,
-            }
-            .into(),
-            Square {
-                center: self.center - self.radius * self.a,
-                radius: self.radius,
-                normal: self.a,
-                orientation: self.b,
-                material: self.material,
+    }
+
+    pub fn center(&self) -> Point3 {
+        self.transform.point(Point3::default())
+    }
+
+    /// Slab test in the local frame: the ray is inside the box where it is between all three
+    /// pairs of planes. Returns where it enters and leaves, which may lie behind the origin,
+    /// together with the axis of the face crossed there.
+    fn slabs(&self, r: Ray) -> Option<((f64, usize), (f64, usize))> {
+        let mut t_near = f64::NEG_INFINITY;
+        let mut t_far = f64::INFINITY;
+        let (mut near_axis, mut far_axis) = (0, 0);
+        for axis in 0..3 {
+            let h = self.half_extents[axis];
+            // Parallel to the slab, where `0 * inf` would give NaN: the ray is either always
+            // between its planes or never.
+            if r.dir[axis] == 0.0 {
+                if r.orig[axis].abs() > h {
+                    return None;
+                }
+                continue;
             }
-            .into(),
-            Square {
-                center: self.center + self.radius * self.b,
-                radius: self.radius,
-                normal: self.b,
-                orientation: self.c,
-                material: self.material,
+            let inv_d = 1.0 / r.dir[axis];
+            let mut t0 = (-h - r.orig[axis]) * inv_d;
+            let mut t1 = (h - r.orig[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
             }
-            .into(),
-            Square {
-                center: self.center - self.radius * self.b,
-                radius: self.radius,
-                normal: self.b,
-                orientation: self.c,
-                material: self.material,
+            if t0 > t_near {
+                t_near = t0;
+                near_axis = axis;
             }
-            .into(),
-            Square {
-                center: self.center + self.radius * self.c,
-                radius: self.radius,
-                normal: self.c,
-                orientation: self.a,
-                material: self.material,
+            if t1 < t_far {
+                t_far = t1;
+                far_axis = axis;
             }
-            .into(),
-            Square {
-                center: self.center - self.radius * self.c,
-                radius: self.radius,
-                normal: self.c,
-                orientation: self.a,
-                material: self.material,
+        }
+        if t_near >= t_far {
+            return None;
+        }
+        Some(((t_near, near_axis), (t_far, far_axis)))
+    }
+
+    fn slabs_local(&self, r: Ray) -> Option<(HitRecord, HitRecord)> {
+        let ((t_near, near_axis), (t_far, far_axis)) = self.slabs(r)?;
+        Some((
+            self.face_hit(r, t_near, near_axis),
+            self.face_hit(r, t_far, far_axis),
+        ))
+    }
+
+    fn face_hit(&self, r: Ray, t: f64, axis: usize) -> HitRecord {
+        let p = r.at(t);
+        let h = self.half_extents;
+        let mut outward_normal = Vec3::default();
+        let side = if p[axis] < 0.0 { -1.0 } else { 1.0 };
+        match axis {
+            0 => outward_normal.x = side,
+            1 => outward_normal.y = side,
+            _ => outward_normal.z = side,
+        }
+        // The two other axes, in cyclic order, span the face.
+        let (j, k) = ((axis + 1) % 3, (axis + 2) % 3);
+        let u = 0.5 * (p[j] / h[j] + 1.0);
+        let v = 0.5 * (p[k] / h[k] + 1.0);
+        HitRecord::new(p, r.dir, t, outward_normal, self.material.clone()).with_uv(u, v)
+    }
+
+    fn hit_local(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (enter, exit) = self.slabs_local(r)?;
+        [enter, exit]
+            .into_iter()
+            .find(|rec| rec.t >= t_min && rec.t <= t_max)
+    }
+}
+
+impl Hittable for Cube {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        hit_transformed(self.transform, r, |local| {
+            self.hit_local(local, t_min, t_max)
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let local = Aabb::centered(Point3::default(), self.half_extents);
+        Some(local.transform(self.transform))
+    }
+
+    fn occluded(&self, r: Ray, t_min: f64, t_max: f64) -> bool {
+        let in_range = |t: f64| t >= t_min && t <= t_max;
+        match self.slabs(to_object_space(self.transform, r)) {
+            Some(((t_near, _), (t_far, _))) => in_range(t_near) || in_range(t_far),
+            None => false,
+        }
+    }
+}
+
+impl Solid for Cube {
+    fn spans(&self, r: Ray) -> Vec<Span> {
+        let local = to_object_space(self.transform, r);
+        match self.slabs_local(local) {
+            Some((enter, exit)) => vec![Span {
+                enter: to_world_space(self.transform, r, enter),
+                exit: to_world_space(self.transform, r, exit),
+            }],
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Straight movement by `offset` between `time0` and `time1`. Before and after that the object
+/// rests at the corresponding end. Without a time range to move in, it stays at the start.
+#[derive(Debug, Copy, Clone)]
+struct LinearMotion {
+    offset: Vec3,
+    time0: f64,
+    time1: f64,
+}
+
+impl LinearMotion {
+    fn offset_at(self, time: f64) -> Vec3 {
+        if self.time1 <= self.time0 {
+            return Vec3::default();
+        }
+        let s = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        s * self.offset
+    }
+
+    /// Moves the ray instead of the object, then moves the hit point back.
+    fn hit<F>(self, r: Ray, hit_static: F) -> Option<HitRecord>
+    where
+        F: FnOnce(Ray) -> Option<HitRecord>,
+    {
+        let mut rec = hit_static(self.shifted(r))?;
+        rec.p += self.offset_at(r.time);
+        Some(rec)
+    }
+
+    fn shifted(self, r: Ray) -> Ray {
+        Ray::new(r.orig - self.offset_at(r.time), r.dir, r.time)
+    }
+
+    fn bounding_box(self, start: Aabb) -> Aabb {
+        let end = Aabb::new(start.min + self.offset, start.max + self.offset);
+        start.surrounding(end)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MovingSphere {
+    sphere: Sphere,
+    motion: LinearMotion,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Point3,
+        center1: Point3,
+        time0: f64,
+        time1: f64,
+        radius: f64,
+        material: Material,
+    ) -> Self {
+        Self {
+            sphere: Sphere::new(center0, radius, material),
+            motion: LinearMotion {
+                offset: center1 - center0,
+                time0,
+                time1,
+            },
+        }
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.motion.hit(r, |r| self.sphere.hit(r, t_min, t_max))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.motion.bounding_box(self.sphere.bounding_box()?))
+    }
+
+    fn occluded(&self, r: Ray, t_min: f64, t_max: f64) -> bool {
+        self.sphere.occluded(self.motion.shifted(r), t_min, t_max)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MovingCube {
+    cube: Cube,
+    motion: LinearMotion,
+}
+
+impl MovingCube {
+    /// Moves `cube` from where it is at `time0` so that it is centered at `center1` at `time1`.
+    pub fn new(cube: Cube, center1: Point3, time0: f64, time1: f64) -> Self {
+        let offset = center1 - cube.center();
+        Self {
+            cube,
+            motion: LinearMotion {
+                offset,
+                time0,
+                time1,
+            },
+        }
+    }
+}
+
+impl Hittable for MovingCube {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.motion.hit(r, |r| self.cube.hit(r, t_min, t_max))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.motion.bounding_box(self.cube.bounding_box()?))
+    }
+
+    fn occluded(&self, r: Ray, t_min: f64, t_max: f64) -> bool {
+        self.cube.occluded(self.motion.shifted(r), t_min, t_max)
+    }
+}
+
+/// Placement of an animated object at a point in time.
+#[derive(Debug, Copy, Clone)]
+pub struct Keyframe {
+    pub time: f64,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Keyframe {
+    fn transform(self) -> Transform {
+        Transform::from_trs(self.translation, self.rotation, self.scale)
+    }
+
+    fn interpolate(self, next: Self, time: f64) -> Transform {
+        let s = (time - self.time) / (next.time - self.time);
+        Transform::from_trs(
+            self.translation + s * (next.translation - self.translation),
+            self.rotation.slerp(next.rotation, s),
+            self.scale + s * (next.scale - self.scale),
+        )
+    }
+}
+
+/// An instance whose transform is interpolated between keyframes at the ray time.
+#[derive(Debug, Clone)]
+pub struct AnimatedInstance {
+    object: Arc<Object>,
+    keyframes: Vec<Keyframe>,
+}
+
+impl AnimatedInstance {
+    pub fn new(object: Arc<Object>, mut keyframes: Vec<Keyframe>) -> Self {
+        assert!(!keyframes.is_empty());
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { object, keyframes }
+    }
+
+    pub fn transform_at(&self, time: f64) -> Transform {
+        let k = &self.keyframes;
+        match k.partition_point(|key| key.time <= time) {
+            0 => k[0].transform(),
+            i if i == k.len() => k[i - 1].transform(),
+            i => k[i - 1].interpolate(k[i], time),
+        }
+    }
+}
+
+impl Hittable for AnimatedInstance {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let transform = self.transform_at(r.time);
+        hit_transformed(transform, r, |local| self.object.hit(local, t_min, t_max))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // Rotations are interpolated along arcs, so bound the object by a sphere around its
+        // origin. Translation and scale are linear, so the keyframes bound the whole motion.
+        let local = self.object.bounding_box()?;
+        let radius = [local.min, local.max]
+            .iter()
+            .map(|p| Vec3::new(p.x.abs(), p.y.abs(), p.z.abs()))
+            .fold(Vec3::default(), |a, p| {
+                Vec3::new(a.x.max(p.x), a.y.max(p.y), a.z.max(p.z))
+            })
+            .length();
+        self.keyframes
+            .iter()
+            .map(|k| {
+                let r = radius * k.scale.x.abs().max(k.scale.y.abs()).max(k.scale.z.abs());
+                Aabb::centered(k.translation, Vec3::new(r, r, r))
+            })
+            .reduce(Aabb::surrounding)
+    }
+
+    fn occluded(&self, r: Ray, t_min: f64, t_max: f64) -> bool {
+        let local = to_object_space(self.transform_at(r.time), r);
+        self.object.occluded(local, t_min, t_max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::materials::Lambertian;
+
+    fn material() -> Material {
+        Lambertian {
+            albedo: Point3::new(0.5, 0.5, 0.5),
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_instance_scaled_sphere() {
+        let sphere = Arc::new(Sphere::new(Point3::default(), 1.0, material()).into());
+        let transform = Transform::translate(Vec3::new(0.0, 0.0, -5.0))
+            * Transform::scale(Vec3::new(1.0, 2.0, 1.0));
+        let instance = Instance::new(sphere, transform);
+
+        // Hits the stretched top of the ellipsoid, whose normal points straight up.
+        let r = Ray::new(Point3::new(0.0, 10.0, -5.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let rec = instance.hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 8.0).abs() < 1e-9);
+        assert!((rec.normal - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+        assert!(rec.front_face);
+    }
+
+    #[test]
+    fn test_rotated_cube() {
+        let cube = Cube::new(
+            Point3::default(),
+            1.0,
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            material(),
+        );
+        // Rotated 45 degrees around z, the nearest edge is sqrt(2) from the center.
+        let r = Ray::new(Point3::new(5.0, 0.0, 0.5), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        let rec = cube.hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - (5.0 - 2f64.sqrt())).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_moving_sphere() {
+        let sphere = MovingSphere::new(
+            Point3::new(0.0, 0.0, -5.0),
+            Point3::new(2.0, 0.0, -5.0),
+            0.0,
+            1.0,
+            0.5,
+            material(),
+        );
+        let r = |time| Ray::new(Point3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), time);
+        assert!(sphere.hit(r(0.0), 0.001, f64::INFINITY).is_none());
+        let rec = sphere.hit(r(1.0), 0.001, f64::INFINITY).unwrap();
+        assert!((rec.p - Point3::new(2.0, 0.0, -4.5)).length() < 1e-9);
+
+        let still = MovingSphere::new(
+            Point3::new(0.0, 0.0, -5.0),
+            Point3::new(2.0, 0.0, -5.0),
+            1.0,
+            1.0,
+            0.5,
+            material(),
+        );
+        assert!(still.hit(r(1.0), 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_keyframe_interpolation() {
+        let sphere = Arc::new(Sphere::new(Point3::default(), 1.0, material()).into());
+        let key = |time, x| Keyframe {
+            time,
+            translation: Vec3::new(x, 0.0, 0.0),
+            rotation: Quat::identity(),
+            scale: Vec3::new(1.0, 1.0, 1.0),
+        };
+        let animated = AnimatedInstance::new(sphere, vec![key(1.0, 4.0), key(0.0, 0.0)]);
+        let p = Point3::default();
+        assert!(
+            (animated.transform_at(0.25).point(p) - Point3::new(1.0, 0.0, 0.0)).length() < 1e-9
+        );
+        assert!((animated.transform_at(2.0).point(p) - Point3::new(4.0, 0.0, 0.0)).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_quad_uv_and_pdf() {
+        let quad = Quad::xz_rect(0.0, 2.0, 0.0, 4.0, 1.0, material());
+        let r = Ray::new(Point3::new(0.5, 3.0, 3.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let rec = quad.hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-12);
+        assert!((rec.u - 0.75).abs() < 1e-12 && (rec.v - 0.25).abs() < 1e-12);
+        assert_eq!(quad.normal(), Vec3::new(0.0, 1.0, 0.0));
+        // Straight on from distance 2: pdf = d^2 / (cos * area).
+        assert!((quad.pdf_value(r.orig, r.dir) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_cube_faces_point_outwards() {
+        let cube = Cube::new(
+            Point3::default(),
+            1.0,
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            material(),
+        );
+        for dir in [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0)] {
+            let spans = cube.spans(Ray::new(-5.0 * dir, dir, 0.0));
+            assert!(spans[0].enter.front_face);
+            assert!(!spans[0].exit.front_face);
+        }
+    }
+
+    #[test]
+    fn test_cuboid_faces() {
+        let cuboid = Cube::cuboid(
+            Point3::new(1.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.5, 1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            material(),
+        );
+        let r = Ray::new(Point3::new(2.0, 5.0, 0.5), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let rec = cuboid.hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 4.5).abs() < 1e-12);
+        assert_eq!(rec.normal, Vec3::new(0.0, 1.0, 0.0));
+        // The top face is spanned by z (u) and x (v).
+        assert!((rec.u - 0.75).abs() < 1e-12 && (rec.v - 0.75).abs() < 1e-12);
+
+        // Along a face, the ray is still between the planes of that face.
+        let r = Ray::new(Point3::new(5.0, 0.5, 0.0), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        assert!((cuboid.hit(r, 0.001, f64::INFINITY).unwrap().t - 2.0).abs() < 1e-12);
+        let r = Ray::new(Point3::new(5.0, 0.6, 0.0), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        assert!(cuboid.hit(r, 0.001, f64::INFINITY).is_none());
+
+        // From inside, only the exit is in range.
+        let r = Ray::new(Point3::new(1.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        let rec = cuboid.hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-12);
+        assert!(!rec.front_face);
+    }
+
+    #[test]
+    fn test_occluded_matches_hit() {
+        let cube: Object = Cube::new(
+            Point3::new(0.0, 0.0, -3.0),
+            1.0,
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            material(),
+        )
+        .into();
+        let world = [
+            cube,
+            Sphere::new(Point3::new(3.0, 0.0, -3.0), 1.0, material()).into(),
+            Quad::xy_rect(-6.0, -4.0, -1.0, 1.0, -3.0, material()).into(),
+        ];
+        let world = &world[..];
+        for x in [-5.0, -2.0, 0.0, 1.5, 3.0, 5.0] {
+            let r = Ray::new(Point3::new(x, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            for t_max in [1.0, 2.5, 10.0] {
+                let hit = world.hit(r, 0.001, t_max).is_some();
+                assert_eq!(
+                    world.occluded(r, 0.001, t_max),
+                    hit,
+                    "x = {x}, t_max = {t_max}"
+                );
             }
-            .into(),
-        ]
-        .as_ref()
-        .hit(r, t_min, t_max)
+        }
     }
 }
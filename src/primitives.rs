@@ -0,0 +1,669 @@
+use crate::aabb::Aabb;
+use crate::materials::Material;
+use crate::objects::{hit_transformed, Hittable};
+use crate::ray::Ray;
+use crate::{HitRecord, Point3, UnitVec3};
+
+use crate::vec3::{Transform, Vec3};
+use std::f64::consts::PI;
+
+/// Rigid frame with its origin at `origin` and its local y axis along `axis`.
+fn frame(origin: Point3, axis: Vec3) -> Transform {
+    let (x, y, z) = basis(axis);
+    Transform::translate(origin) * Transform::from_basis(x, y, z)
+}
+
+/// Right-handed orthonormal basis whose second vector points along `axis`.
+fn basis(axis: Vec3) -> (UnitVec3, UnitVec3, UnitVec3) {
+    let y = axis.unit_vector();
+    let helper = if y.x.abs() > 0.9 {
+        Vec3::new(0.0, 0.0, 1.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let z = helper.cross(y).unit_vector();
+    let x = y.cross(z);
+    (x, y, z)
+}
+
+/// Angle around the local y axis mapped to `[0, 1]`.
+fn azimuth(p: Point3) -> f64 {
+    ((-p.z).atan2(p.x) + PI) / (2.0 * PI)
+}
+
+/// Picks the nearest root in range and builds its record from `surface(p, root_index)`,
+/// which returns the outward normal and the UV coordinates.
+fn nearest<F>(
+    r: Ray,
+    t_min: f64,
+    t_max: f64,
+    material: Material,
+    roots: &[(f64, usize)],
+    surface: F,
+) -> Option<HitRecord>
+where
+    F: Fn(Point3, usize) -> (Vec3, f64, f64),
+{
+    let &(t, part) = roots
+        .iter()
+        .filter(|(t, _)| *t >= t_min && *t <= t_max)
+        .min_by(|a, b| a.0.total_cmp(&b.0))?;
+    let p = r.at(t);
+    let (normal, u, v) = surface(p, part);
+    Some(HitRecord::new(p, r.dir, t, normal, material).with_uv(u, v))
+}
+
+/// Real roots of `a t^2 + b t + c`.
+fn quadratic_roots(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a.abs() < 1e-12 {
+        return if b.abs() < 1e-12 {
+            Vec::new()
+        } else {
+            vec![-c / b]
+        };
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+    // Avoids cancellation between `-b` and the square root.
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    if q == 0.0 {
+        return vec![0.0];
+    }
+    vec![q / a, c / q]
+}
+
+/// Real roots of `t^3 + a t^2 + b t + c`.
+fn cubic_roots(a: f64, b: f64, c: f64) -> Vec<f64> {
+    // Solves for t / scale instead, whose coefficients are at most one in size, so that the
+    // tolerances below hold for roots of any size.
+    let scale = a.abs().max(b.abs().sqrt()).max(c.abs().cbrt());
+    if scale == 0.0 {
+        return vec![0.0];
+    }
+    let (a, b, c) = (a / scale, b / scale.powi(2), c / scale.powi(3));
+    // Depressed cubic y^3 + 3p y + 2q with t = y - a/3.
+    let p = (3.0 * b - a * a) / 9.0;
+    let q = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+    let shift = a / 3.0;
+    let d = q * q + p * p * p;
+    let roots = if d.abs() < 1e-14 {
+        if q.abs() < 1e-14 {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if d < 0.0 {
+        let phi = (-q / (-p * p * p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let m = 2.0 * (-p).sqrt();
+        vec![
+            m * phi.cos(),
+            -m * (phi + PI / 3.0).cos(),
+            -m * (phi - PI / 3.0).cos(),
+        ]
+    } else {
+        let sqrt_d = d.sqrt();
+        vec![(sqrt_d - q).cbrt() - (sqrt_d + q).cbrt()]
+    };
+    roots.into_iter().map(|y| (y - shift) * scale).collect()
+}
+
+/// Real roots of `t^4 + a t^3 + b t^2 + c t + d`, found with Ferrari's method and polished
+/// with a few Newton steps.
+pub fn quartic_roots(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    // Like `cubic_roots`, solves for t / scale so that the tolerances do not depend on the size
+    // of the roots.
+    let scale = a
+        .abs()
+        .max(b.abs().sqrt())
+        .max(c.abs().cbrt())
+        .max(d.abs().sqrt().sqrt());
+    if scale == 0.0 {
+        return vec![0.0];
+    }
+    let (sa, sb, sc, sd) = (
+        a / scale,
+        b / scale.powi(2),
+        c / scale.powi(3),
+        d / scale.powi(4),
+    );
+    // Depressed quartic y^4 + p y^2 + q y + r with t / scale = y - sa/4.
+    let aa = sa * sa;
+    let p = sb - 3.0 * aa / 8.0;
+    let q = sc - sa * sb / 2.0 + aa * sa / 8.0;
+    let r = sd - sa * sc / 4.0 + aa * sb / 16.0 - 3.0 * aa * aa / 256.0;
+
+    let mut ys = if r.abs() < 1e-14 {
+        let mut ys = cubic_roots(0.0, p, q);
+        ys.push(0.0);
+        ys
+    } else {
+        // Any real root of the resolvent cubic splits the quartic into two quadratics, but the
+        // largest keeps `2z - p` clear of rounding below zero.
+        let z = cubic_roots(-p / 2.0, -r, r * p / 2.0 - q * q / 8.0)
+            .into_iter()
+            .fold(f64::NEG_INFINITY, f64::max);
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        if u < -1e-12 || v < -1e-12 {
+            return Vec::new();
+        }
+        let u = u.max(0.0).sqrt();
+        let v = if q < 0.0 {
+            -v.max(0.0).sqrt()
+        } else {
+            v.max(0.0).sqrt()
+        };
+        let mut ys = quadratic_roots(1.0, v, z - u);
+        ys.extend(quadratic_roots(1.0, -v, z + u));
+        ys
+    };
+
+    let f = |t: f64| (((t + a) * t + b) * t + c) * t + d;
+    let df = |t: f64| ((4.0 * t + 3.0 * a) * t + 2.0 * b) * t + c;
+    for y in ys.iter_mut() {
+        let mut t = (*y - sa / 4.0) * scale;
+        for _ in 0..3 {
+            let slope = df(t);
+            if slope == 0.0 {
+                break;
+            }
+            t -= f(t) / slope;
+        }
+        *y = t;
+    }
+    ys
+}
+
+/// Infinite plane through `point`. UVs repeat every unit along the plane.
+#[derive(Debug, Clone)]
+pub struct Plane {
+    point: Point3,
+    normal: UnitVec3,
+    u_axis: UnitVec3,
+    v_axis: UnitVec3,
+    material: Material,
+}
+
+impl Plane {
+    pub fn new(point: Point3, normal: Vec3, material: Material) -> Self {
+        let (u_axis, normal, v_axis) = basis(normal);
+        Self {
+            point,
+            normal,
+            u_axis,
+            v_axis,
+            material,
+        }
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let d = r.dir.dot(self.normal);
+        if d.abs() < 1e-16 {
+            return None;
+        }
+        let t = (self.point - r.orig).dot(self.normal) / d;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let p = r.at(t);
+        let op = p - self.point;
+        let u = op.dot(self.u_axis).rem_euclid(1.0);
+        let v = op.dot(self.v_axis).rem_euclid(1.0);
+        Some(HitRecord::new(p, r.dir, t, self.normal, self.material.clone()).with_uv(u, v))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Disk {
+    plane: Plane,
+    radius: f64,
+}
+
+impl Disk {
+    pub fn new(center: Point3, normal: Vec3, radius: f64, material: Material) -> Self {
+        Self {
+            plane: Plane::new(center, normal, material),
+            radius,
+        }
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let rec = self.plane.hit(r, t_min, t_max)?;
+        let op = rec.p - self.plane.point;
+        if op.length_squared() > self.radius * self.radius {
+            return None;
+        }
+        let u = 0.5 * (op.dot(self.plane.u_axis) / self.radius + 1.0);
+        let v = 0.5 * (op.dot(self.plane.v_axis) / self.radius + 1.0);
+        Some(rec.with_uv(u, v))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let n = self.plane.normal;
+        let extent = |c: f64| self.radius * (1.0 - c * c).max(0.0).sqrt() + 1e-4;
+        Some(Aabb::centered(
+            self.plane.point,
+            Vec3::new(extent(n.x), extent(n.y), extent(n.z)),
+        ))
+    }
+}
+
+/// Roots of the ray against the infinite cylinder `x^2 + z^2 = radius^2`.
+fn cylinder_roots(r: Ray, radius: f64) -> Vec<f64> {
+    let (o, d) = (r.orig, r.dir);
+    quadratic_roots(
+        d.x * d.x + d.z * d.z,
+        2.0 * (o.x * d.x + o.z * d.z),
+        o.x * o.x + o.z * o.z - radius * radius,
+    )
+}
+
+/// Roots of the ray against the sphere around `center`.
+fn sphere_roots(r: Ray, center: Point3, radius: f64) -> Vec<f64> {
+    let oc = r.orig - center;
+    quadratic_roots(
+        r.dir.length_squared(),
+        2.0 * oc.dot(r.dir),
+        oc.length_squared() - radius * radius,
+    )
+}
+
+/// Root of the ray against the plane `y = height` if it lands within `radius` of the axis.
+fn cap_root(r: Ray, height: f64, radius: f64) -> Option<f64> {
+    if r.dir.y.abs() < 1e-12 {
+        return None;
+    }
+    let t = (height - r.orig.y) / r.dir.y;
+    let p = r.at(t);
+    (p.x * p.x + p.z * p.z <= radius * radius).then_some(t)
+}
+
+fn cap_uv(p: Point3, radius: f64) -> (f64, f64) {
+    (0.5 * (p.x / radius + 1.0), 0.5 * (p.z / radius + 1.0))
+}
+
+/// Cylinder with flat caps at both ends.
+#[derive(Debug, Clone)]
+pub struct Cylinder {
+    frame: Transform,
+    height: f64,
+    radius: f64,
+    material: Material,
+}
+
+impl Cylinder {
+    pub fn new(base: Point3, top: Point3, radius: f64, material: Material) -> Self {
+        Self {
+            frame: frame(base, top - base),
+            height: (top - base).length(),
+            radius,
+            material,
+        }
+    }
+
+    fn hit_local(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        const SIDE: usize = 0;
+        const BOTTOM: usize = 1;
+        const TOP: usize = 2;
+        let mut roots: Vec<_> = cylinder_roots(r, self.radius)
+            .into_iter()
+            .filter(|&t| (0.0..=self.height).contains(&r.at(t).y))
+            .map(|t| (t, SIDE))
+            .collect();
+        roots.extend(cap_root(r, 0.0, self.radius).map(|t| (t, BOTTOM)));
+        roots.extend(cap_root(r, self.height, self.radius).map(|t| (t, TOP)));
+
+        nearest(r, t_min, t_max, self.material.clone(), &roots, |p, part| {
+            let (u, v) = cap_uv(p, self.radius);
+            match part {
+                SIDE => (
+                    Vec3::new(p.x, 0.0, p.z) / self.radius,
+                    azimuth(p),
+                    p.y / self.height,
+                ),
+                BOTTOM => (Vec3::new(0.0, -1.0, 0.0), u, v),
+                _ => (Vec3::new(0.0, 1.0, 0.0), u, v),
+            }
+        })
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        hit_transformed(self.frame, r, |local| self.hit_local(local, t_min, t_max))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let local = Aabb::new(
+            Point3::new(-self.radius, 0.0, -self.radius),
+            Point3::new(self.radius, self.height, self.radius),
+        );
+        Some(local.transform(self.frame))
+    }
+}
+
+/// Cone with a flat base, tapering to a point at its apex.
+#[derive(Debug, Clone)]
+pub struct Cone {
+    frame: Transform,
+    height: f64,
+    radius: f64,
+    material: Material,
+}
+
+impl Cone {
+    pub fn new(base: Point3, apex: Point3, radius: f64, material: Material) -> Self {
+        Self {
+            frame: frame(base, apex - base),
+            height: (apex - base).length(),
+            radius,
+            material,
+        }
+    }
+
+    fn hit_local(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        const SIDE: usize = 0;
+        const BASE: usize = 1;
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let (o, d) = (r.orig, r.dir);
+        // x^2 + z^2 = k^2 (height - y)^2
+        let q = self.height - o.y;
+        let mut roots: Vec<_> = quadratic_roots(
+            d.x * d.x + d.z * d.z - k2 * d.y * d.y,
+            2.0 * (o.x * d.x + o.z * d.z + k2 * q * d.y),
+            o.x * o.x + o.z * o.z - k2 * q * q,
+        )
+        .into_iter()
+        .filter(|&t| (0.0..=self.height).contains(&r.at(t).y))
+        .map(|t| (t, SIDE))
+        .collect();
+        roots.extend(cap_root(r, 0.0, self.radius).map(|t| (t, BASE)));
+
+        nearest(r, t_min, t_max, self.material.clone(), &roots, |p, part| {
+            if part == BASE {
+                let (u, v) = cap_uv(p, self.radius);
+                return (Vec3::new(0.0, -1.0, 0.0), u, v);
+            }
+            let rho = (p.x * p.x + p.z * p.z).sqrt();
+            let normal = if rho > 0.0 {
+                Vec3::new(p.x, k * rho, p.z).unit_vector()
+            } else {
+                Vec3::new(0.0, 1.0, 0.0)
+            };
+            (normal, azimuth(p), p.y / self.height)
+        })
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        hit_transformed(self.frame, r, |local| self.hit_local(local, t_min, t_max))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let local = Aabb::new(
+            Point3::new(-self.radius, 0.0, -self.radius),
+            Point3::new(self.radius, self.height, self.radius),
+        );
+        Some(local.transform(self.frame))
+    }
+}
+
+/// All points within `radius` of the segment between two end points.
+#[derive(Debug, Clone)]
+pub struct Capsule {
+    frame: Transform,
+    height: f64,
+    radius: f64,
+    material: Material,
+}
+
+impl Capsule {
+    pub fn new(p0: Point3, p1: Point3, radius: f64, material: Material) -> Self {
+        Self {
+            frame: frame(p0, p1 - p0),
+            height: (p1 - p0).length(),
+            radius,
+            material,
+        }
+    }
+
+    fn hit_local(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let h = self.height;
+        let top = Point3::new(0.0, h, 0.0);
+        let mut roots: Vec<_> = cylinder_roots(r, self.radius)
+            .into_iter()
+            .filter(|&t| (0.0..=h).contains(&r.at(t).y))
+            .collect();
+        roots.extend(
+            sphere_roots(r, Point3::default(), self.radius)
+                .into_iter()
+                .filter(|&t| r.at(t).y < 0.0),
+        );
+        roots.extend(
+            sphere_roots(r, top, self.radius)
+                .into_iter()
+                .filter(|&t| r.at(t).y > h),
+        );
+        let roots: Vec<_> = roots.into_iter().map(|t| (t, 0)).collect();
+
+        nearest(r, t_min, t_max, self.material.clone(), &roots, |p, _| {
+            let axis_point = Point3::new(0.0, p.y.clamp(0.0, h), 0.0);
+            let normal = (p - axis_point) / self.radius;
+            let v = (p.y + self.radius) / (h + 2.0 * self.radius);
+            (normal, azimuth(p), v)
+        })
+    }
+}
+
+impl Hittable for Capsule {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        hit_transformed(self.frame, r, |local| self.hit_local(local, t_min, t_max))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let local = Aabb::new(
+            Point3::new(-self.radius, -self.radius, -self.radius),
+            Point3::new(self.radius, self.height + self.radius, self.radius),
+        );
+        Some(local.transform(self.frame))
+    }
+}
+
+/// Ring around `axis`: a tube of radius `minor` swept along a circle of radius `major`.
+#[derive(Debug, Clone)]
+pub struct Torus {
+    frame: Transform,
+    major: f64,
+    minor: f64,
+    material: Material,
+}
+
+impl Torus {
+    pub fn new(center: Point3, axis: Vec3, major: f64, minor: f64, material: Material) -> Self {
+        Self {
+            frame: frame(center, axis),
+            major,
+            minor,
+            material,
+        }
+    }
+
+    fn hit_local(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (big, small) = (self.major, self.minor);
+        // Start from the bounding sphere with a unit direction to keep the quartic well
+        // conditioned for rays coming from far away.
+        let start = sphere_roots(r, Point3::default(), big + small)
+            .into_iter()
+            .reduce(f64::min)?;
+        let len = r.dir.length();
+        let o = r.at(start);
+        let d = r.dir / len;
+
+        let m = o.dot(d);
+        let n = o.length_squared() + big * big - small * small;
+        let k = 4.0 * big * big;
+        let roots: Vec<_> = quartic_roots(
+            4.0 * m,
+            4.0 * m * m + 2.0 * n - k * (d.x * d.x + d.z * d.z),
+            4.0 * m * n - 2.0 * k * (o.x * d.x + o.z * d.z),
+            n * n - k * (o.x * o.x + o.z * o.z),
+        )
+        .into_iter()
+        .map(|s| (start + s / len, 0))
+        .collect();
+
+        nearest(r, t_min, t_max, self.material.clone(), &roots, |p, _| {
+            let rho = (p.x * p.x + p.z * p.z).sqrt();
+            let ring = Point3::new(p.x, 0.0, p.z) * (big / rho);
+            let normal = (p - ring) / small;
+            let v = (p.y.atan2(rho - big) + PI) / (2.0 * PI);
+            (normal, azimuth(p), v)
+        })
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        hit_transformed(self.frame, r, |local| self.hit_local(local, t_min, t_max))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let extent = self.major + self.minor;
+        let local = Aabb::centered(Point3::default(), Vec3::new(extent, self.minor, extent));
+        Some(local.transform(self.frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::materials::Lambertian;
+
+    fn material() -> Material {
+        Lambertian {
+            albedo: Point3::new(0.5, 0.5, 0.5),
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_quartic_roots() {
+        // (t - 1)(t - 2)(t - 3)(t - 4)
+        let mut roots = quartic_roots(-10.0, 35.0, -50.0, 24.0);
+        roots.sort_by(f64::total_cmp);
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip([1.0, 2.0, 3.0, 4.0]) {
+            assert!((root - expected).abs() < 1e-9);
+        }
+        // t^4 + 1 has no real roots.
+        assert!(quartic_roots(0.0, 0.0, 0.0, 1.0).is_empty());
+
+        // The same roots shrunk to (t - 0.03)(t - 0.06)(t - 0.09)(t - 0.12).
+        let s = 0.03;
+        let mut roots = quartic_roots(
+            -10.0 * s,
+            35.0 * s.powi(2),
+            -50.0 * s.powi(3),
+            24.0 * s.powi(4),
+        );
+        roots.sort_by(f64::total_cmp);
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip([1.0, 2.0, 3.0, 4.0]) {
+            assert!((root - expected * s).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_cubic_roots_of_any_size() {
+        // t^3 - 1e-6 t = t (t - 0.001)(t + 0.001)
+        let mut roots = cubic_roots(0.0, -1e-6, 0.0);
+        roots.sort_by(f64::total_cmp);
+        assert_eq!(roots.len(), 3);
+        for (root, expected) in roots.iter().zip([-1e-3, 0.0, 1e-3]) {
+            assert!((root - expected).abs() < 1e-15);
+        }
+        assert_eq!(cubic_roots(0.0, 0.0, 0.0), [0.0]);
+    }
+
+    #[test]
+    fn test_torus() {
+        let torus = Torus::new(
+            Point3::default(),
+            Vec3::new(0.0, 1.0, 0.0),
+            2.0,
+            0.5,
+            material(),
+        );
+        let r = Ray::new(Point3::new(-10.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let rec = torus.hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 7.5).abs() < 1e-9);
+        assert!((rec.normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-9);
+        // Through the hole.
+        let r = Ray::new(Point3::new(0.0, 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        assert!(torus.hit(r, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_small_torus_hits_are_on_its_surface() {
+        let (major, minor) = (0.02, 0.005);
+        let torus = Torus::new(
+            Point3::default(),
+            Vec3::new(0.0, 1.0, 0.0),
+            major,
+            minor,
+            material(),
+        );
+        for (y, z) in [(0.0, 0.0), (0.002, 0.0), (0.001, 0.015), (-0.004, 0.019)] {
+            let r = Ray::new(Point3::new(-1.0, y, z), Vec3::new(1.0, 0.0, 0.0), 0.0);
+            let rec = torus.hit(r, 0.001, f64::INFINITY).unwrap();
+            let rho = (rec.p.x * rec.p.x + rec.p.z * rec.p.z).sqrt();
+            let distance = ((rho - major).powi(2) + rec.p.y * rec.p.y).sqrt();
+            assert!((distance - minor).abs() < 1e-6 * minor);
+        }
+    }
+
+    #[test]
+    fn test_cylinder_caps_and_side() {
+        let cylinder = Cylinder::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, 2.0),
+            1.0,
+            material(),
+        );
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let rec = cylinder.hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-9);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+
+        let r = Ray::new(Point3::new(5.0, 0.0, 1.0), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        let rec = cylinder.hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 4.0).abs() < 1e-9);
+        assert!((rec.v - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_capsule_bounding_box() {
+        let capsule = Capsule::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 3.0, 0.0),
+            0.5,
+            material(),
+        );
+        let b = capsule.bounding_box().unwrap();
+        assert!((b.min - Point3::new(-0.5, -0.5, -0.5)).length() < 1e-9);
+        assert!((b.max - Point3::new(0.5, 3.5, 0.5)).length() < 1e-9);
+    }
+}
@@ -0,0 +1,1229 @@
+use crate::camera::{Camera, Projection};
+use crate::checkpoint::Checkpoint;
+use crate::film::{Film, Filter};
+use crate::materials::{Material, MaterialProperties};
+use crate::merge::fingerprint;
+use crate::objects::Hittable;
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::stats::{self, Stats};
+use crate::tiles::{tiles, Tile, TileOrder};
+use crate::vec3::Vec3;
+use crate::{random_f64, ray_color, to_rgb8, trace_split, Color, PrimaryHit};
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// What a camera ray computes. Everything but `PathTracer` is a quick, non-physical view for
+/// debugging scenes.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum Integrator {
+    #[default]
+    PathTracer,
+    /// White where the surface is open within `radius`, black where it is occluded.
+    AmbientOcclusion {
+        radius: f64,
+    },
+    /// Outward normals mapped from `[-1, 1]` to `[0, 1]`.
+    Normals,
+    /// Distance from the camera, white up close fading to black at `max`.
+    Depth {
+        max: f64,
+    },
+    Uv,
+    /// A distinct color for every material.
+    MaterialId,
+    /// Green where rays hit the front of a surface, red where they hit the back.
+    FrontFace,
+    /// Number of scattering events, from blue for none to red for the maximum depth.
+    BounceCount,
+}
+
+impl Integrator {
+    pub fn radiance<R: Rng>(self, rng: &mut R, r: Ray, scene: &Scene, max_depth: i32) -> Color {
+        if self == Integrator::PathTracer {
+            return ray_color(rng, r, scene, max_depth);
+        }
+        if self == Integrator::BounceCount {
+            let t = bounces(rng, r, scene, max_depth) as f64 / max_depth.max(1) as f64;
+            return Color::new(t, 0.0, 1.0 - t);
+        }
+
+        let Some(rec) = scene.world().hit(r, 0.001, f64::INFINITY) else {
+            return match self {
+                Integrator::AmbientOcclusion { .. } => Color::new(1.0, 1.0, 1.0),
+                _ => Color::default(),
+            };
+        };
+        match self {
+            Integrator::AmbientOcclusion { radius } => {
+                // Cosine-weighted direction, so the average is the cosine-weighted visibility.
+                let dir = (rec.normal + Vec3::random_unit_vector(rng)).unit_vector();
+                let probe = Ray::new(rec.p, dir, r.time);
+                if scene.world().occluded(probe, 0.001, radius) {
+                    Color::default()
+                } else {
+                    Color::new(1.0, 1.0, 1.0)
+                }
+            }
+            Integrator::Normals => {
+                let outward = if rec.front_face {
+                    rec.normal
+                } else {
+                    -rec.normal
+                };
+                0.5 * (outward + Color::new(1.0, 1.0, 1.0))
+            }
+            Integrator::Depth { max } => {
+                let d = (1.0 - rec.t * r.dir.length() / max).clamp(0.0, 1.0);
+                Color::new(d, d, d)
+            }
+            Integrator::Uv => Color::new(rec.u, rec.v, 0.0),
+            Integrator::MaterialId => hash_color(material_key(rec.material)),
+            Integrator::FrontFace if rec.front_face => Color::new(0.0, 1.0, 0.0),
+            Integrator::FrontFace => Color::new(1.0, 0.0, 0.0),
+            Integrator::PathTracer | Integrator::BounceCount => unreachable!(),
+        }
+    }
+}
+
+/// Parses `path`, `ao[:radius]`, `normals`, `depth[:max]`, `uv`, `material`, `facing` or
+/// `bounces`.
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = match s.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (s, None),
+        };
+        let number = |default: f64| match arg {
+            Some(arg) => arg
+                .parse::<f64>()
+                .map_err(|e| format!("invalid argument for {}: {}", name, e)),
+            None => Ok(default),
+        };
+        Ok(match name {
+            "path" => Integrator::PathTracer,
+            "ao" => Integrator::AmbientOcclusion {
+                radius: number(1.0)?,
+            },
+            "normals" => Integrator::Normals,
+            "depth" => Integrator::Depth { max: number(20.0)? },
+            "uv" => Integrator::Uv,
+            "material" => Integrator::MaterialId,
+            "facing" => Integrator::FrontFace,
+            "bounces" => Integrator::BounceCount,
+            _ => return Err(format!("unknown integrator: {}", s)),
+        })
+    }
+}
+
+/// Follows the path the path tracer would take and counts how often it scatters.
+fn bounces<R: Rng>(rng: &mut R, mut r: Ray, scene: &Scene, max_depth: i32) -> i32 {
+    let mut count = 0;
+    while count < max_depth {
+        let Some(rec) = scene.world().hit(r, 0.001, f64::INFINITY) else {
+            break;
+        };
+        let Some((_, scattered)) = rec.material.scatter(rng, r, &rec) else {
+            break;
+        };
+        r = scattered;
+        count += 1;
+    }
+    count
+}
+
+fn material_key(material: Material) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    std::mem::discriminant(&material).hash(&mut hasher);
+    let fields = match material {
+        Material::Lambertian(l) => vec![l.albedo.x, l.albedo.y, l.albedo.z],
+        Material::Metal(m) => vec![m.albedo.x, m.albedo.y, m.albedo.z, m.fuzz],
+        Material::Dielectric(d) => vec![d.ir],
+        Material::DiffuseLight(d) => vec![d.emit.x, d.emit.y, d.emit.z],
+        Material::Custom(c) => {
+            // Custom materials are identified by their address.
+            (Arc::as_ptr(&c.0) as *const () as usize).hash(&mut hasher);
+            Vec::new()
+        }
+    };
+    for field in fields {
+        field.to_bits().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+fn hash_color(key: u64) -> Color {
+    let channel = |shift: u32| ((key >> shift) & 0xff) as f64 / 255.0;
+    Color::new(channel(0), channel(8), channel(16))
+}
+
+/// Extra framebuffers that can be written alongside the beauty image. They describe the first
+/// surface each camera ray hits and are black where it hits none or scatters off fog first.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Aov {
+    /// How much the surface attenuates the light it scatters.
+    Albedo,
+    /// Shading normal, facing the camera.
+    Normal,
+    Position,
+    /// Distance from the camera.
+    Depth,
+    /// A distinct color for every object in the scene.
+    ObjectId,
+    /// Light emitted by the surface itself, or by the sky where rays escape. Together with the
+    /// lighting passes below it adds up to the beauty image, except where the camera ray
+    /// scatters off fog first and all of them are black.
+    Emission,
+    DirectDiffuse,
+    IndirectDiffuse,
+    DirectSpecular,
+    IndirectSpecular,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 10] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Position,
+        Aov::Depth,
+        Aov::ObjectId,
+        Aov::Emission,
+        Aov::DirectDiffuse,
+        Aov::IndirectDiffuse,
+        Aov::DirectSpecular,
+        Aov::IndirectSpecular,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Position => "position",
+            Aov::Depth => "depth",
+            Aov::ObjectId => "object_id",
+            Aov::Emission => "emission",
+            Aov::DirectDiffuse => "direct_diffuse",
+            Aov::IndirectDiffuse => "indirect_diffuse",
+            Aov::DirectSpecular => "direct_specular",
+            Aov::IndirectSpecular => "indirect_specular",
+        }
+    }
+
+    /// Whether the pass is a part of the light in the beauty image.
+    fn is_light(self) -> bool {
+        matches!(
+            self,
+            Aov::Emission
+                | Aov::DirectDiffuse
+                | Aov::IndirectDiffuse
+                | Aov::DirectSpecular
+                | Aov::IndirectSpecular
+        )
+    }
+
+    /// Value of one sample. Direct light is what reaches the surface straight from lights,
+    /// emitters or the sky; metals and glass count as specular, everything else as diffuse.
+    fn value(self, r: Ray, emitted: Color, primary: Option<&PrimaryHit>) -> Color {
+        let Some(hit) = primary else {
+            return match self {
+                Aov::Emission => emitted,
+                _ => Color::default(),
+            };
+        };
+        let specular = matches!(
+            hit.rec.material,
+            Material::Metal(_) | Material::Dielectric(_)
+        );
+        match self {
+            Aov::Albedo => hit.attenuation,
+            Aov::Normal => hit.rec.normal,
+            Aov::Position => hit.rec.p,
+            Aov::Depth => {
+                let d = hit.rec.t * r.dir.length();
+                Color::new(d, d, d)
+            }
+            Aov::ObjectId => {
+                let mut hasher = DefaultHasher::new();
+                hit.object.hash(&mut hasher);
+                hash_color(hasher.finish())
+            }
+            Aov::Emission => emitted,
+            Aov::DirectDiffuse if !specular => hit.direct,
+            Aov::IndirectDiffuse if !specular => hit.indirect,
+            Aov::DirectSpecular if specular => hit.direct,
+            Aov::IndirectSpecular if specular => hit.indirect,
+            _ => Color::default(),
+        }
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Aov::ALL
+            .into_iter()
+            .find(|aov| aov.name() == s)
+            .ok_or_else(|| format!("unknown AOV: {}", s))
+    }
+}
+
+/// A rendered image: the filtered average of the samples in every pixel, top row first.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: u32,
+    pub beauty: Vec<Color>,
+    pub layers: Vec<(Aov, Vec<Color>)>,
+    /// The work that went into the image.
+    pub stats: Stats,
+}
+
+/// How the two eyes of a stereo pair share one image.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum StereoLayout {
+    /// The left eye on the left half, the right eye on the right half.
+    #[default]
+    SideBySide,
+    /// The left eye on the top half, the right eye on the bottom half.
+    TopBottom,
+}
+
+impl FromStr for StereoLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "side-by-side" => Ok(StereoLayout::SideBySide),
+            "top-bottom" => Ok(StereoLayout::TopBottom),
+            _ => Err(format!("unknown stereo layout: {}", s)),
+        }
+    }
+}
+
+impl Frame {
+    /// Puts the frames of the two eyes, which must have the same size and AOVs, into one.
+    /// It has as many samples per pixel as the eye with fewer.
+    pub fn stereo(left: &Frame, right: &Frame, layout: StereoLayout) -> Frame {
+        assert_eq!((left.width, left.height), (right.width, right.height));
+        let join = |l: &[Color], r: &[Color]| match layout {
+            StereoLayout::SideBySide => l
+                .chunks(left.width)
+                .zip(r.chunks(left.width))
+                .flat_map(|(l, r)| l.iter().chain(r))
+                .copied()
+                .collect(),
+            StereoLayout::TopBottom => [l, r].concat(),
+        };
+        let (width, height) = match layout {
+            StereoLayout::SideBySide => (2 * left.width, left.height),
+            StereoLayout::TopBottom => (left.width, 2 * left.height),
+        };
+        let layers = left
+            .layers
+            .iter()
+            .zip(&right.layers)
+            .map(|((aov, l), (other, r))| {
+                assert_eq!(aov, other);
+                (*aov, join(l, r))
+            })
+            .collect();
+        let mut stats = left.stats;
+        stats += right.stats;
+        Frame {
+            width,
+            height,
+            samples_per_pixel: left.samples_per_pixel.min(right.samples_per_pixel),
+            beauty: join(&left.beauty, &right.beauty),
+            layers,
+            stats,
+        }
+    }
+
+    pub fn layer(&self, aov: Aov) -> Option<&[Color]> {
+        self.layers
+            .iter()
+            .find(|(a, _)| *a == aov)
+            .map(|(_, pixels)| pixels.as_slice())
+    }
+
+    /// Writes one of the images as a binary PPM, gamma-corrected and clamped like the final
+    /// image.
+    pub fn write_ppm<W: Write>(&self, pixels: &[Color], mut out: W) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for c in pixels {
+            out.write_all(&to_rgb8(*c))?;
+        }
+        Ok(())
+    }
+
+    /// Writes one of the images as a little-endian PFM, which keeps the full range of values.
+    pub fn write_pfm<W: Write>(&self, pixels: &[Color], mut out: W) -> io::Result<()> {
+        write!(out, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        // PFM stores the bottom row first.
+        for row in pixels.chunks(self.width).rev() {
+            for c in row {
+                for v in [c.x, c.y, c.z] {
+                    out.write_all(&(v as f32).to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// When a progressive render stops early and how often it takes snapshots and checkpoints.
+/// Without any settings it runs until all samples are taken and takes neither.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Progressive {
+    /// Does not start a pass expected to end later than this, judging by the longest pass so
+    /// far, so the render finishes in time with the same number of samples in every pixel.
+    pub time_budget: Option<Duration>,
+    /// Takes a snapshot after every this many passes.
+    pub snapshot_passes: Option<u32>,
+    /// Takes a snapshot after the first pass that ends this long after the last snapshot.
+    pub snapshot_interval: Option<Duration>,
+    /// Takes a checkpoint after the first pass that ends this long after the last one.
+    pub checkpoint_interval: Option<Duration>,
+}
+
+impl Progressive {
+    /// Whether to take a snapshot after `passes` passes, `since_snapshot` after the last one.
+    fn snapshot_due(&self, passes: u32, since_snapshot: Duration) -> bool {
+        let every_pass = self
+            .snapshot_passes
+            .is_some_and(|n| passes.is_multiple_of(n.max(1)));
+        let every_interval = self
+            .snapshot_interval
+            .is_some_and(|interval| since_snapshot >= interval);
+        every_pass || every_interval
+    }
+}
+
+/// What `Renderer::render_progressive` reports while it runs.
+#[derive(Debug)]
+pub enum Progress<'a> {
+    /// The number of passes finished so far, and the work they took.
+    Pass(u32, Stats),
+    Snapshot(&'a Frame),
+    Checkpoint(&'a Checkpoint),
+}
+
+/// Renders a scene through a camera, tile by tile.
+#[derive(Debug, Clone)]
+pub struct Renderer {
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: u32,
+    pub max_depth: i32,
+    pub integrator: Integrator,
+    pub aovs: Vec<Aov>,
+    pub filter: Filter,
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+    /// Renders with the same settings but different seeds take independent samples.
+    pub seed: u64,
+    /// Scales the light in the image, such as `PhysicalCamera::exposure`.
+    pub exposure: f64,
+}
+
+impl Renderer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            samples_per_pixel: 100,
+            max_depth: 50,
+            integrator: Integrator::default(),
+            aovs: Vec::new(),
+            filter: Filter::default(),
+            tile_size: 32,
+            tile_order: TileOrder::default(),
+            seed: 0,
+            exposure: 1.0,
+        }
+    }
+
+    pub fn with_samples(self, samples_per_pixel: u32) -> Self {
+        Self {
+            samples_per_pixel,
+            ..self
+        }
+    }
+
+    pub fn with_max_depth(self, max_depth: i32) -> Self {
+        Self { max_depth, ..self }
+    }
+
+    pub fn with_integrator(self, integrator: Integrator) -> Self {
+        Self { integrator, ..self }
+    }
+
+    pub fn with_filter(self, filter: Filter) -> Self {
+        Self { filter, ..self }
+    }
+
+    pub fn with_tiles(self, tile_size: usize, tile_order: TileOrder) -> Self {
+        Self {
+            tile_size,
+            tile_order,
+            ..self
+        }
+    }
+
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    pub fn with_exposure(self, exposure: f64) -> Self {
+        Self { exposure, ..self }
+    }
+
+    /// Also accumulates these passes, from the same samples as the beauty image.
+    pub fn with_aovs(self, aovs: &[Aov]) -> Self {
+        Self {
+            aovs: aovs.to_vec(),
+            ..self
+        }
+    }
+
+    /// `on_tile` is called with every tile and its film as soon as the tile is finished.
+    pub fn render<F>(&self, scene: &Scene, camera: &Camera, on_tile: F) -> Frame
+    where
+        F: Fn(Tile, &Film) + Sync,
+    {
+        let film = self.render_film(scene, camera, on_tile);
+        self.frame(&film)
+    }
+
+    pub fn tiles(&self) -> Vec<Tile> {
+        tiles(self.width, self.height, self.tile_size, self.tile_order)
+    }
+
+    /// Renders into a film with the beauty image as the first layer and the AOVs after it.
+    pub fn render_film<F>(&self, scene: &Scene, camera: &Camera, on_tile: F) -> Film
+    where
+        F: Fn(Tile, &Film) + Sync,
+    {
+        let (film, _) = self.render_pass(scene, camera, 0, self.samples_per_pixel, None, on_tile);
+        film
+    }
+
+    /// Everything that affects the image except the number of samples, which a checkpoint is
+    /// only resumed with if it matches.
+    pub fn settings(&self) -> String {
+        format!(
+            "{:?}",
+            Renderer {
+                samples_per_pixel: 0,
+                ..self.clone()
+            }
+        )
+    }
+
+    /// Renders one sample per pixel per pass until `samples_per_pixel` passes are done, the
+    /// time budget runs out or `stop` is set, and returns what was gathered so far, with the
+    /// number of passes as its samples per pixel. Setting `stop` also abandons the tiles of the
+    /// current pass that have not started yet.
+    ///
+    /// The random numbers of a pass only depend on how many passes came before it, so going on
+    /// from a checkpoint in `resume` gives exactly the image an uninterrupted render would. The
+    /// checkpoint must come from a renderer with the same `settings` and the same camera.
+    ///
+    /// `on_progress` hears about every finished pass, and gets snapshots and checkpoints as
+    /// often as `progressive` asks. One last checkpoint is taken if the render ends early.
+    pub fn render_progressive<F>(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        progressive: &Progressive,
+        stop: &AtomicBool,
+        resume: Option<Checkpoint>,
+        on_progress: F,
+    ) -> Frame
+    where
+        F: FnMut(Progress),
+    {
+        self.render_progressive_by(
+            scene,
+            camera,
+            progressive,
+            stop,
+            resume,
+            on_progress,
+            Instant::now,
+        )
+    }
+
+    /// `render_progressive`, keeping time by `now`.
+    #[allow(clippy::too_many_arguments)]
+    fn render_progressive_by<F, C>(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        progressive: &Progressive,
+        stop: &AtomicBool,
+        resume: Option<Checkpoint>,
+        mut on_progress: F,
+        now: C,
+    ) -> Frame
+    where
+        F: FnMut(Progress),
+        C: Fn() -> Instant,
+    {
+        let start = now();
+        let (mut last_snapshot, mut last_checkpoint) = (start, start);
+        let (mut film, mut passes) = match resume {
+            Some(checkpoint) => {
+                debug_assert_eq!(checkpoint.settings, self.settings());
+                debug_assert_eq!(checkpoint.camera, fingerprint(camera));
+                (checkpoint.film, checkpoint.passes)
+            }
+            None => (
+                Film::new(self.width, self.height, 1 + self.aovs.len(), self.filter),
+                0,
+            ),
+        };
+        // Lends the film to the checkpoint rather than copying it.
+        let checkpoint = |film: Film, passes: u32, on_progress: &mut F| {
+            let checkpoint = Checkpoint {
+                settings: self.settings(),
+                camera: fingerprint(camera),
+                passes,
+                film,
+            };
+            on_progress(Progress::Checkpoint(&checkpoint));
+            checkpoint.film
+        };
+
+        let mut longest_pass = Duration::ZERO;
+        while passes < self.samples_per_pixel
+            && !stop.load(Ordering::Relaxed)
+            && progressive
+                .time_budget
+                .is_none_or(|budget| now() - start + longest_pass < budget)
+        {
+            let pass_start = now();
+            let (pass, complete) =
+                self.render_pass(scene, camera, passes, 1, Some(stop), |_, _| {});
+            if !complete {
+                // The partial pass shows in the image, but checkpoints only hold whole passes.
+                let mut partial = checkpoint(film, passes, &mut on_progress);
+                partial.merge(&pass);
+                return self.frame_after(&partial, passes);
+            }
+            film.merge(&pass);
+            passes += 1;
+            on_progress(Progress::Pass(passes, film.stats));
+
+            if progressive.snapshot_due(passes, now() - last_snapshot) {
+                last_snapshot = now();
+                on_progress(Progress::Snapshot(&self.frame_after(&film, passes)));
+            }
+            if progressive
+                .checkpoint_interval
+                .is_some_and(|interval| now() - last_checkpoint >= interval)
+            {
+                last_checkpoint = now();
+                film = checkpoint(film, passes, &mut on_progress);
+            }
+            longest_pass = longest_pass.max(now() - pass_start);
+        }
+        if passes < self.samples_per_pixel {
+            film = checkpoint(film, passes, &mut on_progress);
+        }
+        self.frame_after(&film, passes)
+    }
+
+    /// Renders a stereo pair like `render_progressive` and returns the frames of both eyes.
+    /// The eyes take turns rendering a pass, so both always have the same number of samples:
+    /// when `stop` cuts a pair of passes short, neither eye keeps its pass. The time budget is
+    /// for both eyes together, snapshots show both packed with `layout`, and no checkpoints are
+    /// taken.
+    pub fn render_stereo_progressive<F>(
+        &self,
+        scene: &Scene,
+        eyes: [&Camera; 2],
+        layout: StereoLayout,
+        progressive: &Progressive,
+        stop: &AtomicBool,
+        mut on_progress: F,
+    ) -> [Frame; 2]
+    where
+        F: FnMut(Progress),
+    {
+        let start = Instant::now();
+        let mut last_snapshot = start;
+        let layers = 1 + self.aovs.len();
+        let mut films = eyes.map(|_| Film::new(self.width, self.height, layers, self.filter));
+        let frames = |films: &[Film; 2], passes: u32| {
+            films.each_ref().map(|film| self.frame_after(film, passes))
+        };
+
+        let mut passes = 0;
+        let mut longest_pass = Duration::ZERO;
+        'passes: while passes < self.samples_per_pixel
+            && !stop.load(Ordering::Relaxed)
+            && progressive
+                .time_budget
+                .is_none_or(|budget| start.elapsed() + longest_pass < budget)
+        {
+            let pass_start = Instant::now();
+            let mut pair = Vec::with_capacity(eyes.len());
+            for camera in eyes {
+                let (pass, complete) =
+                    self.render_pass(scene, camera, passes, 1, Some(stop), |_, _| {});
+                if !complete {
+                    break 'passes;
+                }
+                pair.push(pass);
+            }
+            for (film, pass) in films.iter_mut().zip(&pair) {
+                film.merge(pass);
+            }
+            passes += 1;
+            let mut stats = films[0].stats;
+            stats += films[1].stats;
+            on_progress(Progress::Pass(passes, stats));
+
+            if progressive.snapshot_due(passes, last_snapshot.elapsed()) {
+                last_snapshot = Instant::now();
+                let [left, right] = frames(&films, passes);
+                let snapshot = Frame::stereo(&left, &right, layout);
+                on_progress(Progress::Snapshot(&snapshot));
+            }
+            longest_pass = longest_pass.max(pass_start.elapsed());
+        }
+        frames(&films, passes)
+    }
+
+    /// Renders `samples` samples per pixel, tile by tile. The random numbers of each tile only
+    /// depend on where it is, on `pass` and on the seed. Also returns whether every tile was
+    /// rendered.
+    fn render_pass<F>(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        pass: u32,
+        samples: u32,
+        stop: Option<&AtomicBool>,
+        on_tile: F,
+    ) -> (Film, bool)
+    where
+        F: Fn(Tile, &Film) + Sync,
+    {
+        let tiles = self.tiles();
+        let layers = 1 + self.aovs.len();
+        let next = AtomicUsize::new(0);
+        let merger = Mutex::new(TileMerger::new(
+            Film::new(self.width, self.height, layers, self.filter),
+            tiles.len(),
+        ));
+        let stopped = || stop.is_some_and(|stop| stop.load(Ordering::Relaxed));
+        let skipped = AtomicBool::new(false);
+        // Every thread takes the next tile in order until none are left.
+        rayon::scope(|s| {
+            for _ in 0..rayon::current_num_threads() {
+                s.spawn(|_| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(&tile) = tiles.get(index) else {
+                        break;
+                    };
+                    let film = if stopped() {
+                        skipped.store(true, Ordering::Relaxed);
+                        // Skipped tiles still take their turn so the ones after them are merged.
+                        Film::region(self.width, self.height, layers, self.filter, tile)
+                    } else {
+                        let film = self.render_tile(scene, camera, tile, layers, pass, samples);
+                        on_tile(tile, &film);
+                        film
+                    };
+                    merger.lock().unwrap().add(index, film);
+                });
+            }
+        });
+        let film = merger.into_inner().unwrap().film;
+        (film, !skipped.into_inner())
+    }
+
+    /// Each tile splats into its own film, which overlaps its neighbors' as far as the filter
+    /// reaches.
+    pub(crate) fn render_tile(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        tile: Tile,
+        layers: usize,
+        pass: u32,
+        samples: u32,
+    ) -> Film {
+        let position = (tile.y0 * self.width + tile.x0) as u64;
+        // Spreads the seed over all bits, so different seeds practically never give a tile the
+        // same random numbers.
+        let seed = self.seed.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let mut rng = SmallRng::seed_from_u64(position ^ (u64::from(pass) << 40) ^ seed);
+        let mut film = Film::for_tile(self.width, self.height, layers, self.filter, tile);
+        let mut values = vec![Color::default(); layers];
+        // Whatever this thread counted outside of a render is not part of this one.
+        stats::take();
+        for row in tile.y0..tile.y1 {
+            for i in tile.x0..tile.x1 {
+                for _s in 0..samples {
+                    let x = i as f64 + random_f64(&mut rng);
+                    let y = row as f64 + random_f64(&mut rng);
+                    let u = x / self.width as f64;
+                    let v = 1.0 - y / self.height as f64;
+                    let r = camera.get_ray(&mut rng, u, v);
+                    stats::record(|s| s.camera_rays += 1);
+                    self.sample(&mut rng, r, scene, &mut values);
+                    film.add_sample(x, y, &values);
+                }
+            }
+        }
+        film.stats = stats::take();
+        film
+    }
+
+    /// Splits a film rendered by `render_film` into the beauty image and AOVs.
+    pub fn frame(&self, film: &Film) -> Frame {
+        self.frame_after(film, self.samples_per_pixel)
+    }
+
+    fn frame_after(&self, film: &Film, samples_per_pixel: u32) -> Frame {
+        let exposed = |pixels: Vec<Color>, light: bool| -> Vec<Color> {
+            if !light || self.exposure == 1.0 {
+                return pixels;
+            }
+            pixels.into_iter().map(|c| self.exposure * c).collect()
+        };
+        Frame {
+            width: self.width,
+            height: self.height,
+            samples_per_pixel,
+            beauty: exposed(film.layer(0), self.integrator == Integrator::PathTracer),
+            layers: self
+                .aovs
+                .iter()
+                .enumerate()
+                .map(|(i, aov)| (*aov, exposed(film.layer(i + 1), aov.is_light())))
+                .collect(),
+            stats: film.stats,
+        }
+    }
+
+    /// Traces one camera ray, writing its beauty value and then its AOV values to `values`.
+    fn sample<R: Rng>(&self, rng: &mut R, r: Ray, scene: &Scene, values: &mut [Color]) {
+        if self.aovs.is_empty() {
+            values[0] = self.integrator.radiance(rng, r, scene, self.max_depth);
+            return;
+        }
+        let mut primary = None;
+        let (emitted, scattered) = trace_split(rng, r, scene, self.max_depth, None, &mut primary);
+        for (value, aov) in values[1..].iter_mut().zip(&self.aovs) {
+            *value = aov.value(r, emitted, primary.as_ref());
+        }
+        values[0] = match self.integrator {
+            Integrator::PathTracer => emitted + scattered,
+            integrator => integrator.radiance(rng, r, scene, self.max_depth),
+        };
+    }
+}
+
+/// Adds finished tiles to the image in tile order, so the result is the same however the
+/// tiles were scheduled.
+pub(crate) struct TileMerger {
+    pub(crate) film: Film,
+    next: usize,
+    pending: Vec<Option<Film>>,
+}
+
+impl TileMerger {
+    pub(crate) fn new(film: Film, tiles: usize) -> Self {
+        Self {
+            film,
+            next: 0,
+            pending: (0..tiles).map(|_| None).collect(),
+        }
+    }
+
+    /// Whether every tile has been merged.
+    pub(crate) fn is_done(&self) -> bool {
+        self.next == self.pending.len()
+    }
+
+    pub(crate) fn add(&mut self, index: usize, film: Film) {
+        self.pending[index] = Some(film);
+        while let Some(film) = self.pending.get_mut(self.next).and_then(Option::take) {
+            self.film.merge(&film);
+            self.next += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Eye;
+    use crate::materials::Lambertian;
+    use crate::objects::{Quad, Sphere};
+    use crate::Point3;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_debug_integrators() {
+        let material = Lambertian {
+            albedo: Color::new(0.5, 0.5, 0.5),
+        }
+        .into();
+        let scene = Scene::new(vec![Sphere::new(Point3::default(), 1.0, material).into()]);
+        let mut rng = SmallRng::seed_from_u64(0);
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+
+        let normal = Integrator::Normals.radiance(&mut rng, r, &scene, 5);
+        assert_eq!(normal, Color::new(0.5, 0.5, 1.0));
+        let depth = Integrator::Depth { max: 8.0 }.radiance(&mut rng, r, &scene, 5);
+        assert!((depth.x - 0.5).abs() < 1e-12);
+        let facing = Integrator::FrontFace.radiance(&mut rng, r, &scene, 5);
+        assert_eq!(facing, Color::new(0.0, 1.0, 0.0));
+        // Nothing else is near a lone convex shape.
+        let ao = Integrator::AmbientOcclusion { radius: 10.0 }.radiance(&mut rng, r, &scene, 5);
+        assert_eq!(ao, Color::new(1.0, 1.0, 1.0));
+
+        assert_eq!(
+            "ao:0.5".parse::<Integrator>(),
+            Ok(Integrator::AmbientOcclusion { radius: 0.5 })
+        );
+        assert!("nope".parse::<Integrator>().is_err());
+    }
+
+    /// Two spheres, one diffuse and one metal, under an area light.
+    fn lit_spheres() -> (Scene, Camera) {
+        let diffuse: Material = Lambertian {
+            albedo: Color::new(0.5, 0.5, 0.5),
+        }
+        .into();
+        let metal = crate::materials::Metal {
+            albedo: Color::new(0.8, 0.8, 0.8),
+            fuzz: 0.1,
+        }
+        .into();
+        let mut scene = Scene::new(vec![
+            Sphere::new(Point3::new(-1.0, 0.0, 0.0), 1.0, diffuse.clone()).into(),
+            Sphere::new(Point3::new(1.0, 0.0, 0.0), 1.0, metal).into(),
+        ]);
+        let light = Quad::new(
+            Point3::new(-1.0, 3.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            diffuse,
+        );
+        scene.add_area_light(light, Color::new(4.0, 4.0, 4.0));
+        let camera = Camera::new(
+            Point3::new(0.0, 1.0, 6.0),
+            Point3::default(),
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+            1.0,
+            0.0,
+            6.0,
+        );
+        (scene, camera)
+    }
+
+    #[test]
+    fn test_aovs_add_up_to_beauty() {
+        let (scene, camera) = lit_spheres();
+        let frame = Renderer::new(8, 8)
+            .with_samples(4)
+            .with_aovs(&Aov::ALL)
+            .render(&scene, &camera, |_, _| {});
+
+        let lighting = [
+            Aov::Emission,
+            Aov::DirectDiffuse,
+            Aov::IndirectDiffuse,
+            Aov::DirectSpecular,
+            Aov::IndirectSpecular,
+        ];
+        for (i, beauty) in frame.beauty.iter().enumerate() {
+            let sum = lighting.iter().fold(Color::default(), |sum, aov| {
+                sum + frame.layer(*aov).unwrap()[i]
+            });
+            assert!((sum - *beauty).length() < 1e-9);
+        }
+
+        // Exposure scales the light, but not what describes the surfaces.
+        let exposed = Renderer::new(8, 8)
+            .with_samples(4)
+            .with_aovs(&Aov::ALL)
+            .with_exposure(0.5)
+            .render(&scene, &camera, |_, _| {});
+        for (e, b) in exposed.beauty.iter().zip(&frame.beauty) {
+            assert!((*e - 0.5 * *b).length() < 1e-12);
+        }
+        assert_eq!(exposed.layer(Aov::Albedo), frame.layer(Aov::Albedo));
+        let emission = exposed.layer(Aov::Emission).unwrap();
+        assert!((emission[0] - 0.5 * frame.layer(Aov::Emission).unwrap()[0]).length() < 1e-12);
+
+        let mut pfm = Vec::new();
+        frame.write_pfm(&frame.beauty, &mut pfm).unwrap();
+        let header = b"PF\n8 8\n-1.0\n";
+        assert!(pfm.starts_with(header));
+        assert_eq!(pfm.len(), header.len() + 8 * 8 * 3 * 4);
+    }
+
+    #[test]
+    fn test_tile_order_does_not_change_image() {
+        let (scene, camera) = lit_spheres();
+        let renderer = Renderer::new(12, 10)
+            .with_samples(2)
+            .with_filter(Filter::mitchell(2.0));
+        let render = |order| {
+            let finished = AtomicUsize::new(0);
+            let frame = renderer
+                .clone()
+                .with_tiles(4, order)
+                .render(&scene, &camera, |_, _| {
+                    finished.fetch_add(1, Ordering::Relaxed);
+                });
+            assert_eq!(finished.into_inner(), 3 * 3);
+            frame.beauty
+        };
+        let scanline = render(TileOrder::Scanline);
+        for order in [TileOrder::Spiral, TileOrder::Hilbert, TileOrder::Morton] {
+            // Only the order in which overlapping tiles are added up changes.
+            for (a, b) in render(order).iter().zip(&scanline) {
+                assert!((*a - *b).length() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_progressive_passes_and_snapshots() {
+        let (scene, camera) = lit_spheres();
+        let renderer = Renderer::new(8, 6).with_samples(5);
+        let progressive = Progressive {
+            snapshot_passes: Some(2),
+            ..Progressive::default()
+        };
+        let stop = AtomicBool::new(false);
+        let mut passes = Vec::new();
+        let mut snapshots = Vec::new();
+        let frame =
+            renderer.render_progressive(&scene, &camera, &progressive, &stop, None, |progress| {
+                match progress {
+                    Progress::Pass(n, _) => passes.push(n),
+                    Progress::Snapshot(snapshot) => snapshots.push(snapshot.samples_per_pixel),
+                    Progress::Checkpoint(_) => {}
+                }
+            });
+        assert_eq!(passes, [1, 2, 3, 4, 5]);
+        assert_eq!(snapshots, [2, 4]);
+        assert_eq!(frame.samples_per_pixel, 5);
+
+        // Stopping before the first pass leaves an empty image.
+        stop.store(true, Ordering::Relaxed);
+        let frame = renderer.render_progressive(&scene, &camera, &progressive, &stop, None, |_| {});
+        assert_eq!(frame.samples_per_pixel, 0);
+        assert!(frame.beauty.iter().all(|c| *c == Color::default()));
+    }
+
+    #[test]
+    fn test_time_budget_ends_on_a_whole_pass() {
+        let (scene, camera) = lit_spheres();
+        let renderer = Renderer::new(8, 6).with_samples(u32::MAX);
+        let budget = Duration::from_millis(100);
+        let progressive = Progressive {
+            time_budget: Some(budget),
+            ..Progressive::default()
+        };
+        let start = Instant::now();
+        let mut passes = 0;
+        let frame = renderer.render_progressive(
+            &scene,
+            &camera,
+            &progressive,
+            &AtomicBool::new(false),
+            None,
+            |progress| {
+                if let Progress::Pass(n, _) = progress {
+                    passes = n;
+                }
+            },
+        );
+        // Ends on the whole passes it reported, long before running out of samples.
+        assert!(passes > 0);
+        assert_eq!(frame.samples_per_pixel, passes);
+        // A pass of this tiny image takes well under a millisecond, so not starting one that
+        // would overrun keeps the render near the budget. The margin only allows for a badly
+        // loaded machine.
+        assert!(start.elapsed() < budget + Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_time_budget_does_not_start_a_pass_that_would_overrun() {
+        let (scene, camera) = lit_spheres();
+        let renderer = Renderer::new(8, 6).with_samples(u32::MAX);
+        let budget = Duration::from_millis(100);
+        let progressive = Progressive {
+            time_budget: Some(budget),
+            ..Progressive::default()
+        };
+        // Passes take as long as this clock says, which only moves when a pass ends.
+        let start = Instant::now();
+        let clock = Cell::new(start);
+        let pass_times = [10, 40, 10, 10, 10, 10].map(Duration::from_millis);
+        let mut ends = Vec::new();
+        let frame = renderer.render_progressive_by(
+            &scene,
+            &camera,
+            &progressive,
+            &AtomicBool::new(false),
+            None,
+            |progress| {
+                if let Progress::Pass(n, _) = progress {
+                    clock.set(clock.get() + pass_times[n as usize - 1]);
+                    ends.push(clock.get() - start);
+                }
+            },
+            || clock.get(),
+        );
+        // After 50ms, the third pass may take as long as the longest so far and still end at
+        // 90ms. A fourth, starting at 60ms, might end at 100ms and is not started.
+        assert_eq!(ends, [10, 50, 60].map(Duration::from_millis));
+        assert_eq!(frame.samples_per_pixel, 3);
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint_matches_uninterrupted_render() {
+        let (scene, camera) = lit_spheres();
+        let renderer = Renderer::new(8, 6)
+            .with_samples(4)
+            .with_aovs(&[Aov::Normal])
+            .with_filter(Filter::gaussian(1.5))
+            .with_tiles(4, TileOrder::Spiral);
+        let progressive = Progressive::default();
+        let uninterrupted = renderer.render_progressive(
+            &scene,
+            &camera,
+            &progressive,
+            &AtomicBool::new(false),
+            None,
+            |_| {},
+        );
+
+        let stop = AtomicBool::new(false);
+        let mut saved = Vec::new();
+        renderer.render_progressive(&scene, &camera, &progressive, &stop, None, |progress| {
+            match progress {
+                Progress::Pass(2, _) => stop.store(true, Ordering::Relaxed),
+                Progress::Checkpoint(checkpoint) => checkpoint.write_to(&mut saved).unwrap(),
+                _ => {}
+            }
+        });
+        let checkpoint = Checkpoint::read_from(saved.as_slice(), &renderer).unwrap();
+        assert_eq!(checkpoint.passes, 2);
+        assert_eq!(checkpoint.settings, renderer.settings());
+
+        // Checkpoints of other renders are rejected, even when only the film is off.
+        let other = renderer.clone().with_max_depth(3);
+        assert!(Checkpoint::read_from(saved.as_slice(), &other).is_err());
+        let mut huge = saved.clone();
+        let film_start = 8 + 8 + checkpoint.settings.len() + 8 + 8;
+        huge[film_start..film_start + 8].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        let e = Checkpoint::read_from(huge.as_slice(), &renderer).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+
+        let resumed = renderer.render_progressive(
+            &scene,
+            &camera,
+            &progressive,
+            &AtomicBool::new(false),
+            Some(checkpoint),
+            |_| {},
+        );
+        assert_eq!(resumed.samples_per_pixel, 4);
+        assert_eq!(resumed.beauty, uninterrupted.beauty);
+        assert_eq!(resumed.layers, uninterrupted.layers);
+    }
+
+    #[test]
+    fn test_stereo_layouts() {
+        let eye = |value: f64, samples_per_pixel| Frame {
+            width: 2,
+            height: 2,
+            samples_per_pixel,
+            beauty: (0..4).map(|i| Color::new(value, i as f64, 0.0)).collect(),
+            layers: vec![(Aov::Albedo, vec![Color::new(value, 0.0, 0.0); 4])],
+            stats: Stats::default(),
+        };
+        let (left, right) = (eye(0.0, 3), eye(1.0, 2));
+        let side = Frame::stereo(&left, &right, StereoLayout::SideBySide);
+        assert_eq!((side.width, side.height, side.samples_per_pixel), (4, 2, 2));
+        let eyes: Vec<_> = side.beauty.iter().map(|c| (c.x, c.y)).collect();
+        let expected = [
+            (0, 0),
+            (0, 1),
+            (1, 0),
+            (1, 1),
+            (0, 2),
+            (0, 3),
+            (1, 2),
+            (1, 3),
+        ];
+        assert_eq!(eyes, expected.map(|(e, i)| (e as f64, i as f64)));
+        assert_eq!(side.layers[0].1[2], Color::new(1.0, 0.0, 0.0));
+
+        let top = Frame::stereo(&left, &right, StereoLayout::TopBottom);
+        assert_eq!((top.width, top.height), (2, 4));
+        assert_eq!(top.beauty, [left.beauty, right.beauty].concat());
+    }
+
+    #[test]
+    fn test_stopped_stereo_render_keeps_the_eyes_even() {
+        let (scene, camera) = lit_spheres();
+        let eyes = [Eye::Left, Eye::Right].map(|eye| camera.for_eye(eye, 0.3, 4.0).unwrap());
+        let renderer = Renderer::new(8, 6).with_samples(5);
+        let stop = AtomicBool::new(false);
+        let frames = renderer.render_stereo_progressive(
+            &scene,
+            [&eyes[0], &eyes[1]],
+            StereoLayout::SideBySide,
+            &Progressive::default(),
+            &stop,
+            |progress| {
+                if let Progress::Pass(2, _) = progress {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            },
+        );
+        // Both eyes got the passes they would have rendered on their own.
+        let two_passes = renderer.clone().with_samples(2);
+        let expected = eyes.map(|eye| {
+            two_passes.render_progressive(
+                &scene,
+                &eye,
+                &Progressive::default(),
+                &AtomicBool::new(false),
+                None,
+                |_| {},
+            )
+        });
+        for (frame, expected) in frames.iter().zip(&expected) {
+            assert_eq!(frame.samples_per_pixel, 2);
+            assert_eq!(frame.beauty, expected.beauty);
+            assert_eq!(frame.stats, expected.stats);
+        }
+    }
+}
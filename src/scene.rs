@@ -0,0 +1,108 @@
+use crate::fog::Atmosphere;
+use crate::lights::{Light, LightSource, QuadLight};
+use crate::materials::DiffuseLight;
+use crate::objects::{hit_nearest, Hittable, Object, Quad};
+use crate::ray::Ray;
+use crate::stats;
+use crate::{Color, HitRecord, Point3, UnitVec3};
+use rand::Rng;
+
+#[derive(Debug, Default, Clone)]
+pub struct Scene {
+    pub objects: Vec<Object>,
+    pub lights: Vec<Light>,
+    pub atmosphere: Atmosphere,
+    /// Indices into `objects` and `lights` of the area lights, which are both hit by rays and
+    /// sampled directly.
+    pub(crate) area_lights: Vec<(usize, usize)>,
+}
+
+impl Scene {
+    pub fn new(objects: Vec<Object>) -> Self {
+        Self {
+            objects,
+            ..Default::default()
+        }
+    }
+
+    pub fn world(&self) -> &[Object] {
+        &self.objects
+    }
+
+    /// Adds an emitting quad: visible to rays that hit it and sampled directly as a light.
+    /// Its material is replaced by a `DiffuseLight` shining from the front face.
+    pub fn add_area_light(&mut self, quad: Quad, radiance: Color) {
+        let quad = quad.with_material(DiffuseLight { emit: radiance }.into());
+        self.area_lights
+            .push((self.objects.len(), self.lights.len()));
+        self.objects.push(quad.clone().into());
+        self.lights.push(QuadLight { quad, radiance }.into());
+    }
+
+    /// The nearest hit, with the index into `objects` of the object hit.
+    pub fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<(usize, HitRecord)> {
+        hit_nearest(&self.objects, r, t_min, t_max)
+    }
+
+    /// Share of the light emitted by object `index` towards the start of `r` that counts,
+    /// when `r` was scattered with density `scatter_pdf` from where `direct_light` already
+    /// gathered light. Area lights were sampled there too, and the two are weighted against
+    /// each other; other emitters only shine on surfaces through scattered rays.
+    pub(crate) fn emission_weight(&self, index: usize, r: Ray, scatter_pdf: Option<f64>) -> f64 {
+        let Some(scatter_pdf) = scatter_pdf else {
+            return 1.0;
+        };
+        match self.area_lights.iter().find(|(object, _)| *object == index) {
+            Some(&(_, light)) => {
+                mis_weight(scatter_pdf, self.lights[light].pdf_value(r.orig, r.dir))
+            }
+            None => 1.0,
+        }
+    }
+
+    /// Sums the radiance arriving at `p` straight from the lights, each weighted by
+    /// `weight(direction_to_light)`. Shadowed lights contribute nothing and the rest are
+    /// attenuated by the fog in between.
+    ///
+    /// `weight` must also be the density with which the caller scatters rays in that
+    /// direction, as it is for cosine-weighted diffuse surfaces and isotropic fog: lights that
+    /// scattered rays can hit are weighted against them with multiple importance sampling.
+    pub fn direct_light<R, F>(&self, rng: &mut R, p: Point3, time: f64, weight: F) -> Color
+    where
+        R: Rng,
+        F: Fn(UnitVec3) -> f64,
+    {
+        let mut total = Color::default();
+        for light in &self.lights {
+            let (wi, dist, radiance) = light.illuminate(rng, p);
+            let w = weight(wi);
+            if w <= 0.0 || radiance == Color::default() {
+                continue;
+            }
+            let shadow = Ray::new(p, wi, time);
+            stats::record(|s| s.shadow_rays += 1);
+            // Stop short of the light so that an emitting surface does not shadow itself.
+            if self.world().occluded(shadow, 0.001, dist - 0.001) {
+                continue;
+            }
+            let light_pdf = light.pdf_value(p, wi);
+            let mis = if light_pdf > 0.0 {
+                mis_weight(light_pdf, w)
+            } else {
+                1.0
+            };
+            total += mis * w * self.atmosphere.transmittance(shadow, dist) * radiance;
+        }
+        total
+    }
+}
+
+/// Power heuristic weight of a sample taken with density `pdf`, against another strategy that
+/// takes the same sample with density `other`.
+fn mis_weight(pdf: f64, other: f64) -> f64 {
+    if other <= 0.0 {
+        return 1.0;
+    }
+    let (a, b) = (pdf * pdf, other * other);
+    a / (a + b)
+}
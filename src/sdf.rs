@@ -0,0 +1,399 @@
+use crate::aabb::Aabb;
+use crate::materials::Material;
+use crate::objects::Hittable;
+use crate::ray::Ray;
+use crate::{HitRecord, Point3};
+
+use crate::vec3::Vec3;
+use std::sync::Arc;
+
+/// A signed distance field: negative inside the shape, positive outside.
+///
+/// Primitives are centered at the origin; place them with `translate` and combine them with
+/// the operator methods.
+#[derive(Debug, Clone)]
+pub enum Sdf {
+    Sphere {
+        radius: f64,
+    },
+    RoundBox {
+        half_extents: Vec3,
+        radius: f64,
+    },
+    /// Torus lying in the xz plane.
+    Torus {
+        major: f64,
+        minor: f64,
+    },
+    Mandelbulb {
+        power: f64,
+        iterations: u32,
+    },
+    Translate {
+        offset: Vec3,
+        sdf: Arc<Sdf>,
+    },
+    Scale {
+        factor: f64,
+        sdf: Arc<Sdf>,
+    },
+    Union(Arc<Sdf>, Arc<Sdf>),
+    Intersection(Arc<Sdf>, Arc<Sdf>),
+    /// The first shape with the second carved out.
+    Difference(Arc<Sdf>, Arc<Sdf>),
+    SmoothUnion {
+        a: Arc<Sdf>,
+        b: Arc<Sdf>,
+        k: f64,
+    },
+    /// Rotation around the y axis by `amount` radians per unit of height.
+    Twist {
+        amount: f64,
+        sdf: Arc<Sdf>,
+    },
+    /// Infinite copies on a grid with the given cell size. Zero components are not repeated.
+    Repeat {
+        period: Vec3,
+        sdf: Arc<Sdf>,
+    },
+}
+
+impl Sdf {
+    pub fn sphere(radius: f64) -> Self {
+        Sdf::Sphere { radius }
+    }
+
+    pub fn round_box(half_extents: Vec3, radius: f64) -> Self {
+        Sdf::RoundBox {
+            half_extents,
+            radius,
+        }
+    }
+
+    pub fn torus(major: f64, minor: f64) -> Self {
+        Sdf::Torus { major, minor }
+    }
+
+    pub fn mandelbulb(power: f64, iterations: u32) -> Self {
+        Sdf::Mandelbulb { power, iterations }
+    }
+
+    pub fn translate(self, offset: Vec3) -> Self {
+        Sdf::Translate {
+            offset,
+            sdf: Arc::new(self),
+        }
+    }
+
+    pub fn scale(self, factor: f64) -> Self {
+        Sdf::Scale {
+            factor,
+            sdf: Arc::new(self),
+        }
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Sdf::Union(Arc::new(self), Arc::new(other))
+    }
+
+    pub fn intersection(self, other: Self) -> Self {
+        Sdf::Intersection(Arc::new(self), Arc::new(other))
+    }
+
+    pub fn difference(self, other: Self) -> Self {
+        Sdf::Difference(Arc::new(self), Arc::new(other))
+    }
+
+    /// Union that blends the two shapes over a distance of about `k`.
+    pub fn smooth_union(self, other: Self, k: f64) -> Self {
+        Sdf::SmoothUnion {
+            a: Arc::new(self),
+            b: Arc::new(other),
+            k,
+        }
+    }
+
+    pub fn twist(self, amount: f64) -> Self {
+        Sdf::Twist {
+            amount,
+            sdf: Arc::new(self),
+        }
+    }
+
+    pub fn repeat(self, period: Vec3) -> Self {
+        Sdf::Repeat {
+            period,
+            sdf: Arc::new(self),
+        }
+    }
+
+    pub fn distance(&self, p: Point3) -> f64 {
+        match self {
+            Sdf::Sphere { radius } => p.length() - radius,
+            Sdf::RoundBox {
+                half_extents,
+                radius,
+            } => {
+                let q = Vec3::new(
+                    p.x.abs() - half_extents.x,
+                    p.y.abs() - half_extents.y,
+                    p.z.abs() - half_extents.z,
+                );
+                let outside = Vec3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0));
+                outside.length() + q.x.max(q.y).max(q.z).min(0.0) - radius
+            }
+            Sdf::Torus { major, minor } => {
+                let ring = (p.x * p.x + p.z * p.z).sqrt() - major;
+                (ring * ring + p.y * p.y).sqrt() - minor
+            }
+            Sdf::Mandelbulb { power, iterations } => mandelbulb(p, *power, *iterations),
+            Sdf::Translate { offset, sdf } => sdf.distance(p - *offset),
+            Sdf::Scale { factor, sdf } => sdf.distance(p / *factor) * factor,
+            Sdf::Union(a, b) => a.distance(p).min(b.distance(p)),
+            Sdf::Intersection(a, b) => a.distance(p).max(b.distance(p)),
+            Sdf::Difference(a, b) => a.distance(p).max(-b.distance(p)),
+            Sdf::SmoothUnion { a, b, k } => {
+                let (da, db) = (a.distance(p), b.distance(p));
+                let h = (0.5 + 0.5 * (db - da) / k).clamp(0.0, 1.0);
+                db + (da - db) * h - k * h * (1.0 - h)
+            }
+            Sdf::Twist { amount, sdf } => {
+                let (sin, cos) = (amount * p.y).sin_cos();
+                sdf.distance(Vec3::new(cos * p.x - sin * p.z, p.y, sin * p.x + cos * p.z))
+            }
+            Sdf::Repeat { period, sdf } => {
+                let wrap = |v: f64, c: f64| {
+                    if c > 0.0 {
+                        v - c * (v / c).round()
+                    } else {
+                        v
+                    }
+                };
+                sdf.distance(Vec3::new(
+                    wrap(p.x, period.x),
+                    wrap(p.y, period.y),
+                    wrap(p.z, period.z),
+                ))
+            }
+        }
+    }
+
+    /// Box around the surface, or `None` for unbounded fields.
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        let origin = Point3::default();
+        let cube = |r: f64| Some(Aabb::centered(origin, Vec3::new(r, r, r)));
+        match self {
+            Sdf::Sphere { radius } => cube(*radius),
+            Sdf::RoundBox {
+                half_extents,
+                radius,
+            } => Some(Aabb::centered(
+                origin,
+                *half_extents + Vec3::new(*radius, *radius, *radius),
+            )),
+            Sdf::Torus { major, minor } => {
+                let r = major + minor;
+                Some(Aabb::centered(origin, Vec3::new(r, *minor, r)))
+            }
+            // Points further than 2 from the origin escape on the first iteration.
+            Sdf::Mandelbulb { .. } => cube(2.0),
+            Sdf::Translate { offset, sdf } => {
+                let b = sdf.bounding_box()?;
+                Some(Aabb::new(b.min + *offset, b.max + *offset))
+            }
+            Sdf::Scale { factor, sdf } => {
+                let b = sdf.bounding_box()?;
+                Some(Aabb::new(*factor * b.min, *factor * b.max))
+            }
+            Sdf::Union(a, b) => Some(a.bounding_box()?.surrounding(b.bounding_box()?)),
+            Sdf::Intersection(a, b) => match (a.bounding_box(), b.bounding_box()) {
+                (Some(a), Some(b)) => Some(a.overlap(b)),
+                (a, b) => a.or(b),
+            },
+            Sdf::Difference(a, _) => a.bounding_box(),
+            Sdf::SmoothUnion { a, b, k } => {
+                let b = a.bounding_box()?.surrounding(b.bounding_box()?);
+                let pad = Vec3::new(*k, *k, *k);
+                Some(Aabb::new(b.min - pad, b.max + pad))
+            }
+            Sdf::Twist { sdf, .. } => {
+                // Any rotation around y stays within the circle through the farthest corner.
+                let b = sdf.bounding_box()?;
+                let x = b.min.x.abs().max(b.max.x.abs());
+                let z = b.min.z.abs().max(b.max.z.abs());
+                let r = (x * x + z * z).sqrt();
+                Some(Aabb::new(
+                    Point3::new(-r, b.min.y, -r),
+                    Point3::new(r, b.max.y, r),
+                ))
+            }
+            Sdf::Repeat { period, sdf } => {
+                if period.x > 0.0 || period.y > 0.0 || period.z > 0.0 {
+                    None
+                } else {
+                    sdf.bounding_box()
+                }
+            }
+        }
+    }
+
+    /// Outward normal from the central-difference gradient of the field.
+    pub fn normal(&self, p: Point3) -> Vec3 {
+        const H: f64 = 1e-5;
+        let dx = Vec3::new(H, 0.0, 0.0);
+        let dy = Vec3::new(0.0, H, 0.0);
+        let dz = Vec3::new(0.0, 0.0, H);
+        Vec3::new(
+            self.distance(p + dx) - self.distance(p - dx),
+            self.distance(p + dy) - self.distance(p - dy),
+            self.distance(p + dz) - self.distance(p - dz),
+        )
+        .unit_vector()
+    }
+}
+
+fn mandelbulb(p: Point3, power: f64, iterations: u32) -> f64 {
+    let mut z = p;
+    let mut dr = 1.0;
+    let mut r = z.length();
+    for _ in 0..iterations {
+        if r > 2.0 {
+            break;
+        }
+        let theta = (z.z / r).acos() * power;
+        let phi = z.y.atan2(z.x) * power;
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+        let dir = Vec3::new(
+            theta.sin() * phi.cos(),
+            phi.sin() * theta.sin(),
+            theta.cos(),
+        );
+        z = r.powf(power) * dir + p;
+        r = z.length();
+    }
+    0.5 * r.ln() * r / dr
+}
+
+/// A shape defined by a distance field, intersected by sphere tracing.
+#[derive(Debug, Clone)]
+pub struct SdfObject {
+    sdf: Arc<Sdf>,
+    material: Material,
+    max_steps: u32,
+    max_distance: f64,
+    step_scale: f64,
+}
+
+impl SdfObject {
+    pub fn new(sdf: Arc<Sdf>, material: Material) -> Self {
+        Self {
+            sdf,
+            material,
+            max_steps: 256,
+            max_distance: 1000.0,
+            step_scale: 1.0,
+        }
+    }
+
+    /// Limits on the march: rays give up after `max_steps` or past `max_distance`.
+    pub fn with_limits(self, max_steps: u32, max_distance: f64) -> Self {
+        Self {
+            max_steps,
+            max_distance,
+            ..self
+        }
+    }
+
+    /// Shortens every step by this factor. Fields that overestimate the distance, like
+    /// strongly twisted ones, need a value below one to avoid stepping through the surface.
+    pub fn with_step_scale(self, step_scale: f64) -> Self {
+        Self { step_scale, ..self }
+    }
+}
+
+impl Hittable for SdfObject {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        const EPSILON: f64 = 1e-5;
+        let len = r.dir.length();
+        let s_max = (t_max * len).min(self.max_distance);
+        let mut s = t_min * len;
+        // Rays scattered off the surface start on it, and must get clear of it before they
+        // can hit it again; otherwise rays leaving at a grazing angle hit where they start.
+        let mut leaving = self.sdf.distance(r.at(t_min)).abs() < EPSILON;
+        for _ in 0..self.max_steps {
+            if s > s_max {
+                return None;
+            }
+            let t = s / len;
+            let p = r.at(t);
+            // Rays refracted into the shape march through the negative side of the field.
+            let d = self.sdf.distance(p).abs();
+            if d >= 2.0 * EPSILON {
+                leaving = false;
+            }
+            if d < EPSILON && !leaving {
+                return Some(HitRecord::new(
+                    p,
+                    r.dir,
+                    t,
+                    self.sdf.normal(p),
+                    self.material.clone(),
+                ));
+            }
+            s += d.max(EPSILON) * self.step_scale;
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.sdf.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smooth_union_blends() {
+        let a = Sdf::sphere(1.0).translate(Vec3::new(-0.9, 0.0, 0.0));
+        let b = Sdf::sphere(1.0).translate(Vec3::new(0.9, 0.0, 0.0));
+        let hard = a.clone().union(b.clone());
+        let smooth = a.smooth_union(b, 0.5);
+        // The blend fills the crease between the spheres.
+        let crease = Point3::new(0.0, 0.6, 0.0);
+        assert!(smooth.distance(crease) < hard.distance(crease));
+    }
+
+    #[test]
+    fn test_sphere_trace() {
+        let material = crate::materials::Lambertian {
+            albedo: Vec3::new(0.5, 0.5, 0.5),
+        }
+        .into();
+        let shape = SdfObject::new(
+            Arc::new(Sdf::round_box(Vec3::new(1.0, 1.0, 1.0), 0.0)),
+            material,
+        );
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0), 0.0);
+        let rec = shape.hit(r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-4);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-4);
+    }
+
+    #[test]
+    fn test_rays_leaving_the_surface_do_not_hit_it() {
+        let material = crate::materials::Lambertian {
+            albedo: Vec3::new(0.5, 0.5, 0.5),
+        }
+        .into();
+        let object = SdfObject::new(Arc::new(Sdf::sphere(1.0)), material);
+        let grazing = Ray::new(Point3::new(1.0, 0.0, 0.0), Vec3::new(0.001, 1.0, 0.0), 0.0);
+        assert!(object.hit(grazing, 0.001, f64::INFINITY).is_none());
+
+        // Refracted inwards, the ray still finds the far side.
+        let through = Ray::new(Point3::new(1.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0), 0.0);
+        let rec = object.hit(through, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-4);
+    }
+}
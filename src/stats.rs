@@ -0,0 +1,173 @@
+use crate::film::{read_u64, write_u64};
+
+use std::cell::RefCell;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Read, Write};
+use std::ops::AddAssign;
+
+/// Counts of the work done while rendering. Every thread counts on its own, and the counts of
+/// each tile go with its film, so rendering does not slow down from sharing counters and every
+/// render only sees its own. Nothing is counted without the `stats` feature, which is on by
+/// default.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// Rays shot from the camera.
+    pub camera_rays: u64,
+    /// Rays traced through the scene, from the camera or scattered.
+    pub rays: u64,
+    /// Rays testing whether a light is visible.
+    pub shadow_rays: u64,
+    /// Objects of the world tested against rays.
+    pub intersection_tests: u64,
+    /// Paths that escaped to the sky.
+    pub missed: u64,
+    /// Paths that ended on a surface that does not scatter.
+    pub absorbed: u64,
+    /// Paths cut off by the maximum depth.
+    pub depth_capped: u64,
+}
+
+impl Stats {
+    const ZERO: Stats = Stats {
+        camera_rays: 0,
+        rays: 0,
+        shadow_rays: 0,
+        intersection_tests: 0,
+        missed: 0,
+        absorbed: 0,
+        depth_capped: 0,
+    };
+
+    pub fn secondary_rays(&self) -> u64 {
+        self.rays.saturating_sub(self.camera_rays)
+    }
+
+    /// Rays traced through the scene and towards lights.
+    pub fn all_rays(&self) -> u64 {
+        self.rays + self.shadow_rays
+    }
+
+    pub fn paths(&self) -> u64 {
+        self.missed + self.absorbed + self.depth_capped
+    }
+
+    /// Rays traced per path, counting the one from the camera.
+    pub fn average_path_length(&self) -> f64 {
+        self.rays as f64 / self.paths().max(1) as f64
+    }
+
+    pub fn tests_per_ray(&self) -> f64 {
+        self.intersection_tests as f64 / self.all_rays().max(1) as f64
+    }
+
+    pub(crate) fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for n in [
+            self.camera_rays,
+            self.rays,
+            self.shadow_rays,
+            self.intersection_tests,
+            self.missed,
+            self.absorbed,
+            self.depth_capped,
+        ] {
+            write_u64(out, n)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read_from<R: Read>(input: &mut R) -> io::Result<Self> {
+        Ok(Stats {
+            camera_rays: read_u64(input)?,
+            rays: read_u64(input)?,
+            shadow_rays: read_u64(input)?,
+            intersection_tests: read_u64(input)?,
+            missed: read_u64(input)?,
+            absorbed: read_u64(input)?,
+            depth_capped: read_u64(input)?,
+        })
+    }
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Stats) {
+        self.camera_rays += other.camera_rays;
+        self.rays += other.rays;
+        self.shadow_rays += other.shadow_rays;
+        self.intersection_tests += other.intersection_tests;
+        self.missed += other.missed;
+        self.absorbed += other.absorbed;
+        self.depth_capped += other.depth_capped;
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let paths = self.paths().max(1) as f64;
+        let share = |n: u64| 100.0 * n as f64 / paths;
+        writeln!(f, "Primary rays:        {}", self.camera_rays)?;
+        writeln!(f, "Secondary rays:      {}", self.secondary_rays())?;
+        writeln!(f, "Shadow rays:         {}", self.shadow_rays)?;
+        writeln!(f, "Average path length: {:.2}", self.average_path_length())?;
+        writeln!(f, "Paths ending in")?;
+        writeln!(f, "  a miss:            {:5.1}%", share(self.missed))?;
+        writeln!(f, "  absorption:        {:5.1}%", share(self.absorbed))?;
+        writeln!(f, "  the depth cap:     {:5.1}%", share(self.depth_capped))?;
+        write!(f, "Tests per ray:       {:.1}", self.tests_per_ray())
+    }
+}
+
+thread_local! {
+    static LOCAL: RefCell<Stats> = const { RefCell::new(Stats::ZERO) };
+}
+
+/// Counts some work on this thread. Does nothing without the `stats` feature, which leaves
+/// every count at zero.
+pub(crate) fn record<F: FnOnce(&mut Stats)>(f: F) {
+    #[cfg(feature = "stats")]
+    LOCAL.with_borrow_mut(f);
+    #[cfg(not(feature = "stats"))]
+    let _ = f;
+}
+
+/// What this thread counted since the last call, starting again from zero.
+pub(crate) fn take() -> Stats {
+    LOCAL.replace(Stats::ZERO)
+}
+
+#[cfg(all(test, feature = "stats"))]
+mod tests {
+    use super::*;
+    use crate::materials::Lambertian;
+    use crate::objects::Sphere;
+    use crate::ray::Ray;
+    use crate::scene::Scene;
+    use crate::vec3::Vec3;
+    use crate::{ray_color, Color, Point3};
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_paths_end_once() {
+        let mut rng = SmallRng::seed_from_u64(3);
+        let r = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        take();
+        ray_color(&mut rng, r, &Scene::default(), 5);
+        let missed = take();
+        assert_eq!((missed.rays, missed.missed, missed.paths()), (1, 1, 1));
+
+        // Every ray from inside a closed diffuse sphere bounces until the depth runs out.
+        let albedo = Color::new(0.5, 0.5, 0.5);
+        let inside = Scene::new(vec![Sphere::new(
+            Point3::default(),
+            10.0,
+            Lambertian { albedo }.into(),
+        )
+        .into()]);
+        ray_color(&mut rng, r, &inside, 5);
+        let capped = take();
+        assert_eq!((capped.depth_capped, capped.paths()), (1, 1));
+        assert_eq!(capped.rays, 5);
+        assert_eq!(capped.average_path_length(), 5.0);
+        assert_eq!(capped.tests_per_ray(), 1.0);
+    }
+}
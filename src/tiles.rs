@@ -0,0 +1,182 @@
+use std::str::FromStr;
+
+/// A rectangle of pixels, from `(x0, y0)` up to but not including `(x1, y1)`, with `y`
+/// counting rows from the top of the image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Tile {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl Tile {
+    pub fn width(self) -> usize {
+        self.x1 - self.x0
+    }
+
+    pub fn height(self) -> usize {
+        self.y1 - self.y0
+    }
+
+    /// Grows the tile by `margin` pixels on each side, staying within a `width` × `height` image.
+    pub fn padded(self, margin: usize, width: usize, height: usize) -> Self {
+        Tile {
+            x0: self.x0.saturating_sub(margin),
+            y0: self.y0.saturating_sub(margin),
+            x1: (self.x1 + margin).min(width),
+            y1: (self.y1 + margin).min(height),
+        }
+    }
+
+    /// The part covered by both tiles; it may be empty.
+    pub fn overlap(self, other: Self) -> Self {
+        let x0 = self.x0.max(other.x0);
+        let y0 = self.y0.max(other.y0);
+        Tile {
+            x0,
+            y0,
+            x1: self.x1.min(other.x1).max(x0),
+            y1: self.y1.min(other.y1).max(y0),
+        }
+    }
+}
+
+/// The order in which tiles are handed out to render threads.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Rows of tiles from the top, each from left to right.
+    #[default]
+    Scanline,
+    /// Outwards from the center of the image, so the middle shows up first.
+    Spiral,
+    /// Along a Hilbert curve, which keeps consecutive tiles next to each other.
+    Hilbert,
+    /// Along a Z-order curve.
+    Morton,
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scanline" => Ok(TileOrder::Scanline),
+            "spiral" => Ok(TileOrder::Spiral),
+            "hilbert" => Ok(TileOrder::Hilbert),
+            "morton" => Ok(TileOrder::Morton),
+            _ => Err(format!("unknown tile order: {}", s)),
+        }
+    }
+}
+
+/// Splits a `width` × `height` image into tiles of at most `size` × `size` pixels.
+pub fn tiles(width: usize, height: usize, size: usize, order: TileOrder) -> Vec<Tile> {
+    let size = size.max(1);
+    let columns = width.div_ceil(size);
+    let rows = height.div_ceil(size);
+    let mut grid: Vec<(usize, usize)> = (0..rows)
+        .flat_map(|ty| (0..columns).map(move |tx| (tx, ty)))
+        .collect();
+
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            let cx = (columns as f64 - 1.0) / 2.0;
+            let cy = (rows as f64 - 1.0) / 2.0;
+            // Rings of tiles around the center, each walked around by angle.
+            let key = |&(tx, ty): &(usize, usize)| {
+                let (dx, dy) = (tx as f64 - cx, ty as f64 - cy);
+                (dx.abs().max(dy.abs()), dy.atan2(dx))
+            };
+            grid.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+        }
+        TileOrder::Hilbert => {
+            let n = columns.max(rows).next_power_of_two();
+            grid.sort_by_key(|&(tx, ty)| hilbert_index(n, tx, ty));
+        }
+        TileOrder::Morton => grid.sort_by_key(|&(tx, ty)| morton_index(tx, ty)),
+    }
+
+    grid.into_iter()
+        .map(|(tx, ty)| Tile {
+            x0: tx * size,
+            y0: ty * size,
+            x1: ((tx + 1) * size).min(width),
+            y1: ((ty + 1) * size).min(height),
+        })
+        .collect()
+}
+
+/// Distance along the Hilbert curve filling an `n` × `n` grid, `n` being a power of two.
+fn hilbert_index(n: usize, mut x: usize, mut y: usize) -> usize {
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = usize::from(x & s > 0);
+        let ry = usize::from(y & s > 0);
+        d += s * s * ((3 * rx) ^ ry);
+        // Rotates the quadrant so the curve inside it starts where the last one ended.
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+fn morton_index(x: usize, y: usize) -> usize {
+    let spread = |mut v: usize| {
+        let mut out = 0;
+        let mut bit = 0;
+        while v > 0 {
+            out |= (v & 1) << (2 * bit);
+            v >>= 1;
+            bit += 1;
+        }
+        out
+    };
+    spread(x) | (spread(y) << 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_orders_cover_the_image() {
+        for order in [
+            TileOrder::Scanline,
+            TileOrder::Spiral,
+            TileOrder::Hilbert,
+            TileOrder::Morton,
+        ] {
+            let tiles = tiles(100, 70, 16, order);
+            let unique: HashSet<_> = tiles.iter().collect();
+            assert_eq!(unique.len(), 7 * 5);
+            let area: usize = tiles.iter().map(|t| t.width() * t.height()).sum();
+            assert_eq!(area, 100 * 70);
+        }
+    }
+
+    #[test]
+    fn test_hilbert_tiles_are_adjacent() {
+        let tiles = tiles(64, 64, 8, TileOrder::Hilbert);
+        for pair in tiles.windows(2) {
+            let dx = pair[0].x0.abs_diff(pair[1].x0);
+            let dy = pair[0].y0.abs_diff(pair[1].y0);
+            assert_eq!(dx + dy, 8);
+        }
+    }
+
+    #[test]
+    fn test_spiral_starts_in_the_middle() {
+        let first = tiles(56, 56, 8, TileOrder::Spiral)[0];
+        assert_eq!((first.x0, first.y0), (24, 24));
+    }
+}
@@ -1,7 +1,7 @@
-use crate::{random_f64_mm, UnitVec3};
+use crate::{random_f64_mm, Point3, UnitVec3};
 use rand::Rng;
 use std::fmt::{Display, Formatter};
-use std::ops::{Add, AddAssign, Div, DivAssign, Index, Mul, MulAssign, Neg, Sub};
+use std::ops::{Add, AddAssign, Div, DivAssign, Index, Mul, MulAssign, Neg, Sub, SubAssign};
 
 #[derive(PartialEq, Debug, Default, Copy, Clone)]
 pub struct Vec3 {
@@ -56,7 +56,7 @@ impl Vec3 {
     }
 
     pub fn random<R: Rng>(rng: &mut R) -> Self {
-        Self::new(rng.gen(), rng.gen(), rng.gen())
+        Self::new(rng.random(), rng.random(), rng.random())
     }
 
     pub fn random_mm<R: Rng>(rng: &mut R, min: f64, max: f64) -> Self {
@@ -135,6 +135,14 @@ impl AddAssign for Vec3 {
     }
 }
 
+impl SubAssign for Vec3 {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.x -= rhs.x;
+        self.y -= rhs.y;
+        self.z -= rhs.z;
+    }
+}
+
 impl MulAssign<f64> for Vec3 {
     fn mul_assign(&mut self, rhs: f64) {
         self.x *= rhs;
@@ -223,6 +231,520 @@ impl Div<f64> for Vec3 {
     }
 }
 
+/// Row-major 3x3 matrix acting on column vectors.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Mat3 {
+    pub m: [[f64; 3]; 3],
+}
+
+impl Mat3 {
+    pub fn new(m: [[f64; 3]; 3]) -> Self {
+        Mat3 { m }
+    }
+
+    pub fn identity() -> Self {
+        Self::from_diagonal(Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn from_diagonal(d: Vec3) -> Self {
+        Self::new([[d.x, 0.0, 0.0], [0.0, d.y, 0.0], [0.0, 0.0, d.z]])
+    }
+
+    pub fn from_cols(a: Vec3, b: Vec3, c: Vec3) -> Self {
+        Self::new([[a.x, b.x, c.x], [a.y, b.y, c.y], [a.z, b.z, c.z]])
+    }
+
+    pub fn col(self, j: usize) -> Vec3 {
+        Vec3::new(self.m[0][j], self.m[1][j], self.m[2][j])
+    }
+
+    pub fn transpose(self) -> Self {
+        Self::from_cols(
+            Vec3::new(self.m[0][0], self.m[0][1], self.m[0][2]),
+            Vec3::new(self.m[1][0], self.m[1][1], self.m[1][2]),
+            Vec3::new(self.m[2][0], self.m[2][1], self.m[2][2]),
+        )
+    }
+
+    pub fn determinant(self) -> f64 {
+        self.col(0).dot(self.col(1).cross(self.col(2)))
+    }
+
+    pub fn inverse(self) -> Option<Self> {
+        let det = self.determinant();
+        if det.abs() < 1e-300 {
+            return None;
+        }
+        let (a, b, c) = (self.col(0), self.col(1), self.col(2));
+        // The rows of the inverse are the cross products of the other two columns.
+        Some(Self::from_cols(b.cross(c), c.cross(a), a.cross(b)).transpose() * (1.0 / det))
+    }
+}
+
+impl Default for Mat3 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Mul for Mat3 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut m = [[0.0; 3]; 3];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = (0..3).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Mat3::new(m)
+    }
+}
+
+impl Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+
+    fn mul(self, rhs: Vec3) -> Self::Output {
+        let m = self.m;
+        Vec3 {
+            x: m[0][0] * rhs.x + m[0][1] * rhs.y + m[0][2] * rhs.z,
+            y: m[1][0] * rhs.x + m[1][1] * rhs.y + m[1][2] * rhs.z,
+            z: m[2][0] * rhs.x + m[2][1] * rhs.y + m[2][2] * rhs.z,
+        }
+    }
+}
+
+impl Mul<f64> for Mat3 {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        Mat3::new(self.m.map(|row| row.map(|v| v * rhs)))
+    }
+}
+
+/// Quaternion `w + xi + yj + zk`. Rotations are represented by unit quaternions.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Quat {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Quat {
+    pub fn new(w: f64, x: f64, y: f64, z: f64) -> Self {
+        Quat { w, x, y, z }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    /// Rotation by `radians` counter-clockwise around `axis`.
+    pub fn from_axis_angle(axis: Vec3, radians: f64) -> Self {
+        let (sin, cos) = (0.5 * radians).sin_cos();
+        let a = axis.unit_vector() * sin;
+        Self::new(cos, a.x, a.y, a.z)
+    }
+
+    /// Converts an orthonormal rotation matrix.
+    pub fn from_mat3(r: Mat3) -> Self {
+        let m = r.m;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        let q = if trace > 0.0 {
+            let s = 2.0 * (trace + 1.0).sqrt();
+            Self::new(
+                0.25 * s,
+                (m[2][1] - m[1][2]) / s,
+                (m[0][2] - m[2][0]) / s,
+                (m[1][0] - m[0][1]) / s,
+            )
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+            Self::new(
+                (m[2][1] - m[1][2]) / s,
+                0.25 * s,
+                (m[0][1] + m[1][0]) / s,
+                (m[0][2] + m[2][0]) / s,
+            )
+        } else if m[1][1] > m[2][2] {
+            let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+            Self::new(
+                (m[0][2] - m[2][0]) / s,
+                (m[0][1] + m[1][0]) / s,
+                0.25 * s,
+                (m[1][2] + m[2][1]) / s,
+            )
+        } else {
+            let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+            Self::new(
+                (m[1][0] - m[0][1]) / s,
+                (m[0][2] + m[2][0]) / s,
+                (m[1][2] + m[2][1]) / s,
+                0.25 * s,
+            )
+        };
+        q.normalize()
+    }
+
+    /// Rotation turning the -z axis towards `dir`, keeping +y as close to `up` as possible.
+    /// This is the orientation of a camera looking along `dir`.
+    pub fn look_at(dir: Vec3, up: Vec3) -> Self {
+        let w = -dir.unit_vector();
+        let u = up.cross(w).unit_vector();
+        let v = w.cross(u);
+        Self::from_mat3(Mat3::from_cols(u, v, w))
+    }
+
+    pub fn dot(self, rhs: Self) -> f64 {
+        self.w * rhs.w + self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+    }
+
+    pub fn length(self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(self) -> Self {
+        self * (1.0 / self.length())
+    }
+
+    pub fn conjugate(self) -> Self {
+        Self::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    /// Spherical linear interpolation along the shorter arc between two rotations.
+    pub fn slerp(self, rhs: Self, t: f64) -> Self {
+        let mut cos = self.dot(rhs);
+        let mut end = rhs;
+        if cos < 0.0 {
+            cos = -cos;
+            end = -rhs;
+        }
+        if cos > 0.9995 {
+            // Nearly parallel: fall back to normalized linear interpolation.
+            return (self * (1.0 - t) + end * t).normalize();
+        }
+        let theta = cos.acos();
+        let sin = theta.sin();
+        self * (((1.0 - t) * theta).sin() / sin) + end * ((t * theta).sin() / sin)
+    }
+
+    pub fn to_mat3(self) -> Mat3 {
+        let Quat { w, x, y, z } = self;
+        Mat3::new([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ])
+    }
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Neg for Quat {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Quat::new(-self.w, -self.x, -self.y, -self.z)
+    }
+}
+
+impl Add for Quat {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Quat::new(
+            self.w + rhs.w,
+            self.x + rhs.x,
+            self.y + rhs.y,
+            self.z + rhs.z,
+        )
+    }
+}
+
+impl Mul<f64> for Quat {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        Quat::new(self.w * rhs, self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+impl Mul for Quat {
+    type Output = Self;
+
+    /// Hamilton product: `a * b` rotates by `b` first, then by `a`.
+    fn mul(self, rhs: Self) -> Self::Output {
+        Quat::new(
+            self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+            self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+        )
+    }
+}
+
+impl Mul<Vec3> for Quat {
+    type Output = Vec3;
+
+    /// Rotates a vector by a unit quaternion.
+    fn mul(self, rhs: Vec3) -> Self::Output {
+        let q = Vec3::new(self.x, self.y, self.z);
+        let t = 2.0 * q.cross(rhs);
+        rhs + self.w * t + q.cross(t)
+    }
+}
+
+/// Row-major 4x4 matrix acting on column vectors.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Mat4 { m }
+    }
+
+    pub fn identity() -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = self.m[j][i];
+            }
+        }
+        Self::new(m)
+    }
+
+    /// Gauss-Jordan elimination with partial pivoting. Returns `None` for singular matrices.
+    pub fn inverse(self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Self::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() < 1e-300 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let d = a[col][col];
+            for j in 0..4 {
+                a[col][j] /= d;
+                inv[col][j] /= d;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let f = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= f * a[col][j];
+                        inv[row][j] -= f * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Self::new(inv))
+    }
+
+    /// The linear part, dropping translation and projection.
+    pub fn upper_left(self) -> Mat3 {
+        let m = self.m;
+        Mat3::new([
+            [m[0][0], m[0][1], m[0][2]],
+            [m[1][0], m[1][1], m[1][2]],
+            [m[2][0], m[2][1], m[2][2]],
+        ])
+    }
+
+    pub fn transform_point(self, p: Vec3) -> Vec3 {
+        let m = self.m;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+        let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        if w == 1.0 {
+            Vec3::new(x, y, z)
+        } else {
+            Vec3::new(x, y, z) / w
+        }
+    }
+
+    pub fn transform_vector(self, v: Vec3) -> Vec3 {
+        let m = self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+
+impl From<Mat3> for Mat4 {
+    fn from(a: Mat3) -> Self {
+        let m = a.m;
+        Mat4::new([
+            [m[0][0], m[0][1], m[0][2], 0.0],
+            [m[1][0], m[1][1], m[1][2], 0.0],
+            [m[2][0], m[2][1], m[2][2], 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Mat4::new(m)
+    }
+}
+
+/// An affine transform together with its inverse.
+#[derive(PartialEq, Debug, Default, Copy, Clone)]
+pub struct Transform {
+    m: Mat4,
+    inv: Mat4,
+}
+
+impl Transform {
+    pub fn new(m: Mat4) -> Option<Self> {
+        Some(Transform {
+            m,
+            inv: m.inverse()?,
+        })
+    }
+
+    pub fn identity() -> Self {
+        Self::default()
+    }
+
+    pub fn translate(offset: Vec3) -> Self {
+        let mut m = Mat4::identity();
+        let mut inv = Mat4::identity();
+        for i in 0..3 {
+            m.m[i][3] = offset[i];
+            inv.m[i][3] = -offset[i];
+        }
+        Transform { m, inv }
+    }
+
+    pub fn scale(factors: Vec3) -> Self {
+        let mut m = Mat4::identity();
+        let mut inv = Mat4::identity();
+        for i in 0..3 {
+            m.m[i][i] = factors[i];
+            inv.m[i][i] = 1.0 / factors[i];
+        }
+        Transform { m, inv }
+    }
+
+    /// Rotation by `degrees` counter-clockwise around `axis`.
+    pub fn rotate(axis: Vec3, degrees: f64) -> Self {
+        Self::from_rotation(Quat::from_axis_angle(axis, degrees.to_radians()))
+    }
+
+    pub fn from_rotation(q: Quat) -> Self {
+        let m = Mat4::from(q.to_mat3());
+        Transform {
+            m,
+            inv: m.transpose(),
+        }
+    }
+
+    /// Scales, then rotates, then translates.
+    pub fn from_trs(translation: Vec3, rotation: Quat, scale: Vec3) -> Self {
+        Self::translate(translation) * Self::from_rotation(rotation) * Self::scale(scale)
+    }
+
+    /// Places an object (such as a camera) at `eye` with its -z axis pointing at `target`.
+    pub fn look_at(eye: Point3, target: Point3, up: Vec3) -> Self {
+        Self::translate(eye) * Self::from_rotation(Quat::look_at(target - eye, up))
+    }
+
+    /// Maps the coordinate axes onto the orthonormal basis `a`, `b`, `c`.
+    pub fn from_basis(a: UnitVec3, b: UnitVec3, c: UnitVec3) -> Self {
+        let m = Mat4::from(Mat3::from_cols(a, b, c));
+        Transform {
+            m,
+            inv: m.transpose(),
+        }
+    }
+
+    pub fn matrix(self) -> Mat4 {
+        self.m
+    }
+
+    pub fn inverse(self) -> Self {
+        Transform {
+            m: self.inv,
+            inv: self.m,
+        }
+    }
+
+    pub fn point(self, p: Vec3) -> Vec3 {
+        self.m.transform_point(p)
+    }
+
+    pub fn vector(self, v: Vec3) -> Vec3 {
+        self.m.transform_vector(v)
+    }
+
+    /// Normals transform by the inverse transpose. The result is not normalized.
+    pub fn normal(self, n: Vec3) -> Vec3 {
+        self.inv.upper_left().transpose() * n
+    }
+}
+
+impl Mul for Transform {
+    type Output = Self;
+
+    /// `a * b` applies `b` first, then `a`.
+    fn mul(self, rhs: Self) -> Self::Output {
+        Transform {
+            m: self.m * rhs.m,
+            inv: rhs.inv * self.inv,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
@@ -241,4 +763,80 @@ mod tests {
         let vec2 = Vec3::new(0.0, 1.0, 0.0);
         assert_eq!(vec1.cross(vec2), Vec3::new(0.0, 0.0, 1.0));
     }
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_mat3_inverse() {
+        let m = Mat3::new([[2.0, 0.0, 1.0], [1.0, 3.0, 0.0], [0.0, 1.0, 4.0]]);
+        let v = Vec3::new(1.0, -2.0, 0.5);
+        assert_near(m.inverse().unwrap() * (m * v), v);
+        assert_eq!(
+            Mat3::from_diagonal(Vec3::new(1.0, 0.0, 1.0)).inverse(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_mat4_inverse() {
+        let t = Transform::from_trs(
+            Vec3::new(1.0, 2.0, 3.0),
+            Quat::from_axis_angle(Vec3::new(1.0, 1.0, 0.0), 0.7),
+            Vec3::new(2.0, 0.5, 1.5),
+        );
+        let p = Vec3::new(-1.0, 4.0, 2.0);
+        let inv = t.matrix().inverse().unwrap();
+        assert_near(inv.transform_point(t.point(p)), p);
+        assert_near(t.inverse().point(t.point(p)), p);
+    }
+
+    #[test]
+    fn test_quat_rotation() {
+        let q = Quat::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), std::f64::consts::FRAC_PI_2);
+        assert_near(q * Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_near(
+            q.to_mat3() * Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        let r = Quat::from_mat3(q.to_mat3());
+        assert!((r.dot(q).abs() - 1.0).abs() < 1e-9);
+        assert_near(
+            (q * q) * Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn test_quat_slerp() {
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let a = Quat::identity();
+        let b = Quat::from_axis_angle(axis, 2.0);
+        let half = a.slerp(b, 0.5);
+        let expected = Quat::from_axis_angle(axis, 1.0);
+        assert!((half.dot(expected) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_look_at() {
+        let q = Quat::look_at(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_near(q * Vec3::new(0.0, 0.0, -1.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_near(q * Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let t = Transform::look_at(
+            Point3::new(0.0, 0.0, 5.0),
+            Point3::default(),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        assert_near(t.point(Vec3::new(0.0, 0.0, -5.0)), Point3::default());
+    }
+
+    #[test]
+    fn test_transform_normal() {
+        // A plane tilted 45 degrees, squashed along x: the normal must stay perpendicular.
+        let t = Transform::scale(Vec3::new(0.5, 1.0, 1.0));
+        let tangent = Vec3::new(1.0, -1.0, 0.0);
+        let normal = Vec3::new(1.0, 1.0, 0.0);
+        assert!(t.vector(tangent).dot(t.normal(normal)).abs() < 1e-12);
+    }
 }